@@ -1,56 +1,279 @@
 use crate::*;
 
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct AdSpot {
     pub adspot_id: u64,
     pub owner_account_id: AccountId,
     pub price: Balance,
+    pub pricing: PricingModel,
+    /// Fungible token contracts the Ad Spot can be paid with, and the price in each token
+    pub accepted_tokens: HashMap<AccountId, U128>,
     pub name: String,
     pub publisher_earn: Option<u64>,
     pub show_kind: Option<String>, 
+    pub cancellation_penalty: Option<u64>,
+    /// Delisted spots take no new bookings, existing ones are still honored
+    pub listed: bool,
+    /// Only creatives approved by a moderator can be booked
+    pub approved_creatives_only: bool,
+    /// Creative formats the Ad Spot can show, any creative when empty
+    pub accepted_formats: Vec<AcceptedFormat>,
+}
+
+/// Optional fields `update_adspot` can set back to `None`
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum AdSpotField {
+    PublisherEarn,
+    ShowKind,
+    CancellationPenalty,
 }
 
 #[near_bindgen]
 impl MetaAdsContract {
 
+    /// The storage of the Ad Spot is paid from the storage balance of the caller,
+    /// or from the attached deposit. The unused deposit is refunded.
+    /// The id is assigned by the contract unless `adspot_id` is given.
+    #[payable]
     pub fn make_adspot(
         &mut self, 
-        adspot_id: u64, 
+        adspot_id: Option<u64>, 
         price: Balance,
         name: String, 
         publisher_earn: Option<u64>, 
-        show_kind: Option<String>
+        show_kind: Option<String>,
+        cancellation_penalty: Option<u64>,
+        pricing: Option<PricingModel>,
+        accepted_tokens: Option<HashMap<AccountId, U128>>,
+        approved_creatives_only: Option<bool>,
+        accepted_formats: Option<Vec<AcceptedFormat>>,
     ) -> AdSpot {
         
-        assert!(adspot_id > 0, "Abort. Playback Id undefined");
+        let adspot_id = next_id(&mut self.next_adspot_id, &mut self.taken_adspot_ids, adspot_id);
+        assert!(adspot_id > 0, "Abort. Ad Spot Id undefined");
         assert!(price > 0, "Abort. Price undefined");
-        assert!(name != "", "Abort. Name is empty");
-        assert!(name.len() <= 100, "Abort. Name is longer than 100 characters");
+        assert_valid_adspot_name(&name);
+        assert_valid_cancellation_penalty(cancellation_penalty);
+        let accepted_tokens = accepted_tokens.unwrap_or_default();
+        assert_valid_accepted_tokens(&accepted_tokens);
+        let accepted_formats = accepted_formats.unwrap_or_default();
+        assert_valid_accepted_formats(&accepted_formats);
 
         let owner_account_id: AccountId = env::predecessor_account_id();
+        let storage_before = env::storage_usage();
         let ad_spot = AdSpot {
             adspot_id,
-            owner_account_id,
+            owner_account_id: owner_account_id.clone(),
             price: price * SPOT_NEAR,
+            pricing: pricing.unwrap_or_default(),
+            accepted_tokens,
             name,
             publisher_earn,
             show_kind,
+            cancellation_penalty,
+            listed: true,
+            approved_creatives_only: approved_creatives_only.unwrap_or(false),
+            accepted_formats,
         };
 
         assert!(
             self.ad_spots.insert(&adspot_id, &ad_spot).is_none(),
             "Ad Spot already exists"
         );
+        self.internal_index_adspot(&ad_spot);
+        EventLog::new(EventLogVariant::AdspotCreated(vec![ad_spot.clone()])).emit();
+
+        let unused = self.internal_update_storage(&owner_account_id, storage_before, env::attached_deposit());
+        refund_unused_deposit(unused);
 
         ad_spot
     }
 
-    pub fn fetch_all_adspots(&self) -> HashMap<u64, AdSpot> {
-        self.ad_spots.iter().collect()
+    /// Only the given fields change, the fields in `clear` are set to `None`.
+    /// Presentations already booked keep their price.
+    #[payable]
+    pub fn update_adspot(
+        &mut self,
+        adspot_id: u64,
+        price: Option<Balance>,
+        name: Option<String>,
+        publisher_earn: Option<u64>,
+        show_kind: Option<String>,
+        cancellation_penalty: Option<u64>,
+        pricing: Option<PricingModel>,
+        accepted_tokens: Option<HashMap<AccountId, U128>>,
+        approved_creatives_only: Option<bool>,
+        accepted_formats: Option<Vec<AcceptedFormat>>,
+        clear: Option<Vec<AdSpotField>>,
+    ) -> AdSpot {
+        let mut ad_spot = self.internal_adspot_for_owner(adspot_id);
+        let storage_before = env::storage_usage();
+
+        for field in clear.unwrap_or_default() {
+            let set = match field {
+                AdSpotField::PublisherEarn => publisher_earn.is_some(),
+                AdSpotField::ShowKind => show_kind.is_some(),
+                AdSpotField::CancellationPenalty => cancellation_penalty.is_some(),
+            };
+            assert!(!set, "Abort. {:?} cannot be set and cleared at once", field);
+            match field {
+                AdSpotField::PublisherEarn => ad_spot.publisher_earn = None,
+                AdSpotField::ShowKind => ad_spot.show_kind = None,
+                AdSpotField::CancellationPenalty => ad_spot.cancellation_penalty = None,
+            }
+        }
+        if let Some(price) = price {
+            assert!(price > 0, "Abort. Price undefined");
+            ad_spot.price = price * SPOT_NEAR;
+        }
+        if let Some(pricing) = pricing {
+            ad_spot.pricing = pricing;
+        }
+        if let Some(accepted_tokens) = accepted_tokens {
+            assert_valid_accepted_tokens(&accepted_tokens);
+            ad_spot.accepted_tokens = accepted_tokens;
+        }
+        if let Some(name) = name {
+            assert_valid_adspot_name(&name);
+            ad_spot.name = name;
+        }
+        if publisher_earn.is_some() {
+            ad_spot.publisher_earn = publisher_earn;
+        }
+        if show_kind.is_some() {
+            ad_spot.show_kind = show_kind;
+        }
+        if let Some(accepted_formats) = accepted_formats {
+            assert_valid_accepted_formats(&accepted_formats);
+            ad_spot.accepted_formats = accepted_formats;
+        }
+        if let Some(approved_creatives_only) = approved_creatives_only {
+            ad_spot.approved_creatives_only = approved_creatives_only;
+        }
+        if cancellation_penalty.is_some() {
+            assert_valid_cancellation_penalty(cancellation_penalty);
+            ad_spot.cancellation_penalty = cancellation_penalty;
+        }
+
+        self.ad_spots.insert(&adspot_id, &ad_spot);
+        EventLog::new(EventLogVariant::AdspotUpdated(vec![ad_spot.clone()])).emit();
+
+        let unused = self.internal_update_storage(&ad_spot.owner_account_id, storage_before, env::attached_deposit());
+        refund_unused_deposit(unused);
+
+        ad_spot
+    }
+
+    /// Stops new bookings on the Ad Spot. Presentations already booked are still honored.
+    pub fn delist_adspot(&mut self, adspot_id: u64) -> AdSpot {
+        let mut ad_spot = self.internal_adspot_for_owner(adspot_id);
+        assert!(ad_spot.listed, "Abort. Ad Spot is already delisted");
+
+        ad_spot.listed = false;
+        self.ad_spots.insert(&adspot_id, &ad_spot);
+        EventLog::new(EventLogVariant::AdspotUpdated(vec![ad_spot.clone()])).emit();
+
+        ad_spot
     }
 
     pub fn fetch_adspot_by_id(&self, id: u64) -> AdSpot {
         self.ad_spots.get(&id).expect("AdSpot not found")
     }
-}
\ No newline at end of file
+}
+
+impl AdSpot {
+    /// The price in NEAR, or in `token_id`
+    pub fn price_in(&self, token_id: &Option<AccountId>) -> Balance {
+        match token_id {
+            Some(token_id) => self
+                .accepted_tokens
+                .get(token_id)
+                .unwrap_or_else(|| env::panic(format!("Abort. Ad Spot does not accept {}", token_id).as_bytes()))
+                .0,
+            None => self.price,
+        }
+    }
+}
+
+impl MetaAdsContract {
+    fn internal_adspot_for_owner(&self, adspot_id: u64) -> AdSpot {
+        assert!(adspot_id > 0, "Abort. AdSpot Id undefined");
+
+        let ad_spot = self.ad_spots.get(&adspot_id).expect("AdSpot not found");
+        assert_eq!(
+            ad_spot.owner_account_id,
+            env::predecessor_account_id(),
+            "Abort. Only the Ad Spot owner can change it"
+        );
+
+        ad_spot
+    }
+}
+
+fn assert_valid_adspot_name(name: &str) {
+    assert!(!name.is_empty(), "Abort. Name is empty");
+    assert!(name.len() <= 100, "Abort. Name is longer than 100 characters");
+}
+
+fn assert_valid_cancellation_penalty(cancellation_penalty: Option<u64>) {
+    assert!(
+        cancellation_penalty.unwrap_or(0) <= 100,
+        "Abort. Cancellation penalty must be a percentage between 0 and 100"
+    );
+}
+
+fn assert_valid_accepted_tokens(accepted_tokens: &HashMap<AccountId, U128>) {
+    for (token_id, price) in accepted_tokens.iter() {
+        assert!(env::is_valid_account_id(token_id.as_bytes()), "Abort. Invalid token account id {}", token_id);
+        assert!(price.0 > 0, "Abort. Price in {} undefined", token_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> (MetaAdsContract, AdSpot) {
+        set_caller("publisher.near", NEAR, 0);
+        let mut contract = new_contract();
+        let adspot = contract.make_adspot(
+            None,
+            1,
+            "Banner".to_string(),
+            Some(5),
+            Some(ROTATION_SHOW_KIND.to_string()),
+            Some(20),
+            None,
+            None,
+            None,
+            None,
+        );
+        (contract, adspot)
+    }
+
+    #[test]
+    fn update_clears_optional_fields() {
+        let (mut contract, adspot) = setup();
+        assert!(adspot.allows_rotation());
+
+        let clear = vec![AdSpotField::ShowKind, AdSpotField::CancellationPenalty];
+        let updated = contract.update_adspot(adspot.adspot_id, None, None, None, None, None, None, None, None, None, Some(clear));
+
+        assert_eq!(updated.show_kind, None);
+        assert_eq!(updated.cancellation_penalty, None);
+        assert_eq!(updated.publisher_earn, Some(5));
+        assert!(!contract.fetch_adspot_by_id(adspot.adspot_id).allows_rotation());
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. PublisherEarn cannot be set and cleared at once")]
+    fn update_cannot_set_and_clear_a_field() {
+        let (mut contract, adspot) = setup();
+        let clear = vec![AdSpotField::PublisherEarn];
+        contract.update_adspot(adspot.adspot_id, None, None, Some(7), None, None, None, None, None, None, Some(clear));
+    }
+}
@@ -0,0 +1,178 @@
+use crate::*;
+use std::ops::Bound;
+
+/// `AdSpot.show_kind` of spots that rotate several creatives in the same window
+pub const ROTATION_SHOW_KIND: &str = "rotation";
+
+/// Bookings of one Ad Spot keyed by (start_time, playback_id), the value is the end time
+pub type Bookings = TreeMap<(Timestamp, u64), Timestamp>;
+
+/// A window of the Ad Spot schedule. Booked windows carry the presentation id, free ones don't.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ScheduleWindow {
+    pub start_time: Timestamp,
+    pub end_time: Timestamp,
+    pub playback_id: Option<u64>,
+}
+
+impl AdSpot {
+    pub fn allows_rotation(&self) -> bool {
+        self.show_kind.as_deref() == Some(ROTATION_SHOW_KIND)
+    }
+}
+
+#[near_bindgen]
+impl MetaAdsContract {
+
+    /// Booked and free windows of the Ad Spot between `from` and `to`
+    pub fn fetch_adspot_schedule(&self, adspot_id: u64, from: Timestamp, to: Timestamp) -> Vec<ScheduleWindow> {
+        assert!(from < to, "Abort. Start of the range must be less than its end");
+
+        let bookings = match self.bookings_per_adspot.get(&adspot_id) {
+            Some(bookings) => bookings,
+            None => {
+                return vec![ScheduleWindow { start_time: from, end_time: to, playback_id: None }];
+            }
+        };
+
+        // Bookings starting before `from - longest` end before `from`
+        let longest = self.longest_booking_per_adspot.get(&adspot_id).unwrap_or(0);
+        let mut schedule = vec![];
+        let mut free_from = from;
+        for ((start_time, playback_id), end_time) in bookings
+            .range((Bound::Included((from.saturating_sub(longest), 0)), Bound::Unbounded))
+            .take_while(|((start_time, _), _)| *start_time < to)
+        {
+            if end_time <= from {
+                continue;
+            }
+            if start_time > free_from {
+                schedule.push(ScheduleWindow { start_time: free_from, end_time: start_time, playback_id: None });
+            }
+            schedule.push(ScheduleWindow { start_time, end_time, playback_id: Some(playback_id) });
+            free_from = free_from.max(end_time);
+        }
+        if free_from < to {
+            schedule.push(ScheduleWindow { start_time: free_from, end_time: to, playback_id: None });
+        }
+
+        schedule
+    }
+}
+
+impl MetaAdsContract {
+    /// Rejects a window that overlaps an existing booking, unless the spot rotates creatives
+    pub(crate) fn internal_assert_window_free(&self, adspot: &AdSpot, start_time: Timestamp, end_time: Timestamp) {
+        if adspot.allows_rotation() {
+            return;
+        }
+
+        if let Some(bookings) = self.bookings_per_adspot.get(&adspot.adspot_id) {
+            // Bookings can overlap each other, made while the spot rotated creatives or before
+            // the calendar existed, so any booking starting before `end_time` can reach into the window.
+            // None of them is longer than the longest booking, which bounds how far back to look.
+            let longest = self.longest_booking_per_adspot.get(&adspot.adspot_id).unwrap_or(0);
+            let overlapping = bookings
+                .iter_rev_from((end_time, 0))
+                .take_while(|((booked_from, _), _)| booked_from.saturating_add(longest) > start_time)
+                .find(|(_, booked_until)| *booked_until > start_time);
+            if let Some(((booked_from, playback_id), booked_until)) = overlapping {
+                env::panic(
+                    format!(
+                        "Abort. Ad Spot is already booked from {} to {} by presentation {}",
+                        booked_from, booked_until, playback_id
+                    )
+                    .as_bytes(),
+                );
+            }
+        }
+    }
+
+    pub(crate) fn internal_add_booking(&mut self, presentation: &Presentation) {
+        let mut bookings = self.bookings_per_adspot.get(&presentation.adspot_id).unwrap_or_else(|| {
+            TreeMap::new(
+                StorageKey::BookingsInner { adspot_id: presentation.adspot_id }
+                    .try_to_vec()
+                    .unwrap(),
+            )
+        });
+        bookings.insert(&(presentation.start_time, presentation.playback_id), &presentation.end_time);
+        self.bookings_per_adspot.insert(&presentation.adspot_id, &bookings);
+
+        let duration = presentation.end_time.saturating_sub(presentation.start_time);
+        if duration > self.longest_booking_per_adspot.get(&presentation.adspot_id).unwrap_or(0) {
+            self.longest_booking_per_adspot.insert(&presentation.adspot_id, &duration);
+        }
+    }
+
+    pub(crate) fn internal_remove_booking(&mut self, presentation: &Presentation) {
+        if let Some(mut bookings) = self.bookings_per_adspot.get(&presentation.adspot_id) {
+            bookings.remove(&(presentation.start_time, presentation.playback_id));
+            self.bookings_per_adspot.insert(&presentation.adspot_id, &bookings);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup(show_kind: Option<String>) -> (MetaAdsContract, AdSpot) {
+        set_caller("publisher.near", NEAR, 0);
+        let mut contract = new_contract();
+        let adspot = contract.make_adspot(None, 1, "Banner".to_string(), None, show_kind, None, None, None, None, None);
+        (contract, adspot)
+    }
+
+    /// Overlapping bookings, like the ones left by a spot that rotated creatives
+    fn book_overlapping(contract: &mut MetaAdsContract, adspot_id: u64) {
+        let mut bookings = TreeMap::new(StorageKey::BookingsInner { adspot_id }.try_to_vec().unwrap());
+        bookings.insert(&(0, 1), &1000);
+        bookings.insert(&(10, 2), &20);
+        bookings.insert(&(2000, 3), &3000);
+        contract.bookings_per_adspot.insert(&adspot_id, &bookings);
+        contract.longest_booking_per_adspot.insert(&adspot_id, &1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Ad Spot is already booked from 0 to 1000 by presentation 1")]
+    fn window_inside_an_earlier_booking() {
+        let (mut contract, adspot) = setup(None);
+        book_overlapping(&mut contract, adspot.adspot_id);
+
+        contract.internal_assert_window_free(&adspot, 500, 600);
+    }
+
+    #[test]
+    fn free_windows_between_bookings() {
+        let (mut contract, adspot) = setup(None);
+        book_overlapping(&mut contract, adspot.adspot_id);
+
+        contract.internal_assert_window_free(&adspot, 1000, 2000);
+        contract.internal_assert_window_free(&adspot, 3000, 4000);
+    }
+
+    #[test]
+    fn schedule_shows_bookings_started_before_the_range() {
+        let (mut contract, adspot) = setup(None);
+        book_overlapping(&mut contract, adspot.adspot_id);
+
+        assert_eq!(
+            contract.fetch_adspot_schedule(adspot.adspot_id, 500, 1500),
+            vec![
+                ScheduleWindow { start_time: 0, end_time: 1000, playback_id: Some(1) },
+                ScheduleWindow { start_time: 1000, end_time: 1500, playback_id: None },
+            ]
+        );
+    }
+
+    #[test]
+    fn rotation_spots_take_overlapping_windows() {
+        let (mut contract, adspot) = setup(Some(ROTATION_SHOW_KIND.to_string()));
+        book_overlapping(&mut contract, adspot.adspot_id);
+
+        contract.internal_assert_window_free(&adspot, 500, 600);
+    }
+}
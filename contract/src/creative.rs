@@ -1,46 +1,377 @@
 use crate::*;
 
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+/// Length of a sha256 content hash
+pub const CONTENT_HASH_LEN: usize = 32;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Creative {
     pub creative_id: u64,
     pub name: String,
     pub content: String,
+    /// Base64-encoded sha256 hash of the content, or of the media it links to
+    pub content_hash: Base64VecU8,
+    /// Starts at 1, every content change adds a version
+    pub version: u32,
+    pub format: Option<CreativeFormat>,
+    /// Every new version goes back to `Pending`
+    pub moderation: ModerationStatus,
+    /// Archived creatives stay viewable for their presentations but cannot be booked
+    pub archived: bool,
     pub nft_cid: Option<String>,
+    /// NFT the advertiser must hold to create and book the creative
+    pub nft: Option<NftReference>,
     pub owner_account_id: AccountId,
 }
 
+/// The content of a creative at one version
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreativeVersion {
+    pub version: u32,
+    pub content: String,
+    pub content_hash: Base64VecU8,
+    pub format: Option<CreativeFormat>,
+    pub created_at: Timestamp,
+}
+
 #[near_bindgen]
 impl MetaAdsContract {
 
-    pub fn make_creative(&mut self, name: String, content: String, creative_id: u64, nft_cid: Option<String>) -> Creative {
-        assert!(name != "", "Abort. Name is empty");
-        assert!(name.len() <= 100, "Abort. Name is longer than 100 characters");
-        assert!(content != "", "Abort. Content is empty");
+    /// The storage of the creative is paid from the storage balance of the caller,
+    /// or from the attached deposit. The unused deposit is refunded.
+    /// The id is assigned by the contract unless `creative_id` is given.
+    /// `content_hash` defaults to the sha256 of `content`. Pass the hash of the media
+    /// when the content links to it.
+    /// With an `nft` the creative is created once the NFT contract confirms the caller
+    /// holds the token, so the creative is the result of the returned promise.
+    #[payable]
+    pub fn make_creative(
+        &mut self,
+        name: String,
+        content: String,
+        content_hash: Option<Base64VecU8>,
+        creative_id: Option<u64>,
+        nft_cid: Option<String>,
+        nft: Option<NftReference>,
+        format: Option<CreativeFormat>,
+    ) -> PromiseOrValue<Option<Creative>> {
+        assert_valid_creative_name(&name);
+        assert!(!content.is_empty(), "Abort. Content is empty");
+        if let Some(format) = &format {
+            assert_valid_creative_format(format);
+        }
+        let creative_id = next_id(&mut self.next_creative_id, &mut self.taken_creative_ids, creative_id);
         assert!(creative_id > 0, "Abort. Creative Id undefined");
+        assert!(self.creatives.get(&creative_id).is_none(), "Creative already exists");
+        assert!(!self.deleted_creatives.contains(&creative_id), "Abort. Creative {} was deleted", creative_id);
 
-        let owner_account_id: AccountId = env::predecessor_account_id();
         let creative = Creative {
             creative_id,
             name,
+            content_hash: content_hash_or_sha256(&content, content_hash),
             content,
+            version: 1,
+            format,
+            moderation: ModerationStatus::Pending,
+            archived: false,
             nft_cid,
-            owner_account_id,
+            nft: nft.clone(),
+            owner_account_id: env::predecessor_account_id(),
         };
 
-        assert!(
-            self.creatives.insert(&creative_id, &creative).is_none(),
-            "Creative already exists"
-        );
+        if let Some(nft) = nft {
+            assert_valid_nft_reference(&nft);
+            return PromiseOrValue::Promise(self.internal_add_creative_with_nft(&nft, creative, env::attached_deposit()));
+        }
+
+        let unused = self.internal_add_creative(&creative, env::attached_deposit());
+        refund_unused_deposit(unused);
+
+        PromiseOrValue::Value(Some(creative))
+    }
+
+    /// Only the given fields change. A new `content`, `content_hash` or `format` adds a version that
+    /// waits for moderation, presentations already booked keep the version they were booked with.
+    #[payable]
+    pub fn update_creative(
+        &mut self,
+        creative_id: u64,
+        name: Option<String>,
+        content: Option<String>,
+        content_hash: Option<Base64VecU8>,
+        format: Option<CreativeFormat>,
+    ) -> Creative {
+        let mut creative = self.internal_creative_for_owner(creative_id);
+        let storage_before = env::storage_usage();
+
+        if let Some(name) = name {
+            assert_valid_creative_name(&name);
+            creative.name = name;
+        }
+        if content.is_some() || content_hash.is_some() || format.is_some() {
+            let content = content.unwrap_or_else(|| creative.content.clone());
+            assert!(!content.is_empty(), "Abort. Content is empty");
+            creative.content_hash = content_hash_or_sha256(&content, content_hash);
+            creative.content = content;
+            if let Some(format) = format {
+                assert_valid_creative_format(&format);
+                creative.format = Some(format);
+            }
+            creative.version += 1;
+            self.internal_add_creative_version(&creative);
+            self.internal_set_moderation(&mut creative, ModerationStatus::Pending);
+        }
+
+        self.creatives.insert(&creative_id, &creative);
+        EventLog::new(EventLogVariant::CreativeUpdated(vec![creative.clone()])).emit();
+
+        let unused = self.internal_update_storage(&creative.owner_account_id, storage_before, env::attached_deposit());
+        refund_unused_deposit(unused);
 
         creative
     }
 
-    pub fn fetch_all_creatives(&self) -> HashMap<u64, Creative> {
-        self.creatives.iter().collect()
+    pub fn archive_creative(&mut self, creative_id: u64) -> Creative {
+        let mut creative = self.internal_creative_for_owner(creative_id);
+        assert!(!creative.archived, "Abort. Creative is already archived");
+
+        creative.archived = true;
+        self.creatives.insert(&creative_id, &creative);
+        EventLog::new(EventLogVariant::CreativeUpdated(vec![creative.clone()])).emit();
+
+        creative
+    }
+
+    /// Removes the creative with all its versions. Refused while a presentation of the
+    /// creative is not over. The freed storage becomes available for `storage_withdraw`.
+    /// The id is never reused, its presentations stay listed under it.
+    pub fn delete_creative(&mut self, creative_id: u64) {
+        let creative = self.internal_creative_for_owner(creative_id);
+
+        if let Some(playback_ids) = self.presentations_per_creative.get(&creative_id) {
+            for playback_id in playback_ids.iter() {
+                let presentation = self.presentations.get(&playback_id).unwrap();
+                assert!(
+                    presentation.status.is_terminal(),
+                    "Abort. Creative is used by presentation {} which is {}",
+                    playback_id,
+                    presentation.status
+                );
+            }
+        }
+
+        let storage_before = env::storage_usage();
+
+        self.creatives.remove(&creative_id);
+        for version in 1..=creative.version {
+            self.creative_versions.remove(&(creative_id, version));
+        }
+        self.pending_creatives.remove(&creative_id);
+        self.deleted_creatives.insert(&creative_id);
+        self.internal_unindex_creative(&creative);
+        EventLog::new(EventLogVariant::CreativeDeleted(vec![CreativeDeletedLog {
+            creative_id,
+            owner_id: creative.owner_account_id.clone(),
+        }]))
+        .emit();
+
+        self.internal_update_storage(&creative.owner_account_id, storage_before, 0);
     }
 
     pub fn fetch_creative_by_id(&self, id: u64) -> Creative {
         self.creatives.get(&id).expect("Creative not found")
     }
-}    
\ No newline at end of file
+
+    pub fn fetch_creative_version(&self, creative_id: u64, version: u32) -> CreativeVersion {
+        self.creative_versions
+            .get(&(creative_id, version))
+            .expect("Creative version not found")
+    }
+
+    /// Versions of the creative, oldest first
+    pub fn fetch_creative_versions(&self, creative_id: u64, from_index: Option<U128>, limit: Option<u64>) -> Vec<CreativeVersion> {
+        let creative = self.fetch_creative_by_id(creative_id);
+        page_range(u64::from(creative.version), from_index, limit)
+            .map(|index| self.fetch_creative_version(creative_id, index as u32 + 1))
+            .collect()
+    }
+}
+
+impl MetaAdsContract {
+    fn internal_creative_for_owner(&self, creative_id: u64) -> Creative {
+        let creative = self.fetch_creative_by_id(creative_id);
+        assert_eq!(
+            creative.owner_account_id,
+            env::predecessor_account_id(),
+            "Abort. Only the owner can change the creative"
+        );
+        creative
+    }
+
+    /// Stores the creative and charges its owner for the storage.
+    /// Returns the part of `attached` that was not needed.
+    pub(crate) fn internal_add_creative(&mut self, creative: &Creative, attached: Balance) -> Balance {
+        let storage_before = env::storage_usage();
+
+        assert!(
+            self.creatives.insert(&creative.creative_id, creative).is_none(),
+            "Creative already exists"
+        );
+        self.internal_add_creative_version(creative);
+        self.internal_index_creative(creative);
+        if creative.moderation == ModerationStatus::Pending {
+            self.pending_creatives.insert(&creative.creative_id);
+        }
+        EventLog::new(EventLogVariant::CreativeCreated(vec![creative.clone()])).emit();
+
+        self.internal_update_storage(&creative.owner_account_id, storage_before, attached)
+    }
+
+    /// Records the current content of the creative as its current version
+    pub(crate) fn internal_add_creative_version(&mut self, creative: &Creative) {
+        let version = CreativeVersion {
+            version: creative.version,
+            content: creative.content.clone(),
+            content_hash: creative.content_hash.clone(),
+            format: creative.format.clone(),
+            created_at: env::block_timestamp() / 1000000000,
+        };
+        self.creative_versions.insert(&(creative.creative_id, creative.version), &version);
+    }
+}
+
+fn assert_valid_creative_name(name: &str) {
+    assert!(!name.is_empty(), "Abort. Name is empty");
+    assert!(name.len() <= 100, "Abort. Name is longer than 100 characters");
+}
+
+/// The given hash of the content, or the sha256 of `content`
+pub(crate) fn content_hash_or_sha256(content: &str, content_hash: Option<Base64VecU8>) -> Base64VecU8 {
+    match content_hash {
+        Some(content_hash) => {
+            assert_eq!(
+                content_hash.0.len(),
+                CONTENT_HASH_LEN,
+                "Abort. Content hash must be {} bytes",
+                CONTENT_HASH_LEN
+            );
+            content_hash
+        }
+        None => Base64VecU8(env::sha256(content.as_bytes())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() -> MetaAdsContract {
+        set_caller("advertiser.near", NEAR, 0);
+        new_contract()
+    }
+
+    #[test]
+    fn update_creative_adds_versions() {
+        let mut contract = setup();
+        let creative = make_creative(&mut contract, "ipfs://v1");
+        assert_eq!(creative.version, 1);
+        assert_eq!(creative.content_hash.0, env::sha256(b"ipfs://v1"));
+
+        let renamed = contract.update_creative(creative.creative_id, Some("Renamed".to_string()), None, None, None);
+        assert_eq!(renamed.version, 1);
+        assert_eq!(contract.fetch_moderation_queue_count(), U128(1));
+
+        let media_hash = Base64VecU8(vec![7; CONTENT_HASH_LEN]);
+        let updated = contract.update_creative(creative.creative_id, None, Some("ipfs://v2".to_string()), Some(media_hash.clone()), None);
+        assert_eq!(updated.version, 2);
+        assert_eq!(updated.content_hash, media_hash);
+
+        let versions = contract.fetch_creative_versions(creative.creative_id, None, None);
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].content, "ipfs://v1");
+        assert_eq!(versions[0].content_hash.0, env::sha256(b"ipfs://v1"));
+        assert_eq!(versions[1].content, "ipfs://v2");
+    }
+
+    #[test]
+    fn delete_creative_releases_storage() {
+        let mut contract = setup();
+        let creative = make_creative(&mut contract, "ipfs://v1");
+        contract.update_creative(creative.creative_id, None, Some("ipfs://v2".to_string()), None, None);
+        let used_bytes = contract.storage_accounts.get(&creative.owner_account_id).unwrap().used_bytes;
+
+        contract.delete_creative(creative.creative_id);
+
+        assert!(contract.creatives.get(&creative.creative_id).is_none());
+        assert!(contract.creative_versions.get(&(creative.creative_id, 1)).is_none());
+        assert_eq!(contract.fetch_creatives_count_for_owner(creative.owner_account_id.clone()), U128(0));
+        assert!(contract.storage_accounts.get(&creative.owner_account_id).unwrap().used_bytes < used_bytes);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Creative 1 was deleted")]
+    fn deleted_creative_id_is_not_reused() {
+        let mut contract = setup();
+        let creative = make_creative(&mut contract, "ipfs://v1");
+        contract.delete_creative(creative.creative_id);
+
+        contract.make_creative("Banner".to_string(), "ipfs://v2".to_string(), None, Some(creative.creative_id), None, None, None);
+    }
+
+    fn make_creative_with_id(contract: &mut MetaAdsContract, creative_id: Option<u64>) -> u64 {
+        match contract.make_creative("Banner".to_string(), "ipfs://v1".to_string(), None, creative_id, None, None, None) {
+            PromiseOrValue::Value(Some(creative)) => creative.creative_id,
+            _ => panic!("creative not created"),
+        }
+    }
+
+    #[test]
+    fn generated_ids_skip_explicit_ones() {
+        let mut contract = setup();
+        assert_eq!(make_creative_with_id(&mut contract, Some(2)), 2);
+        assert_eq!(make_creative_with_id(&mut contract, Some(100)), 100);
+
+        assert_eq!(make_creative_with_id(&mut contract, None), 1);
+        assert_eq!(make_creative_with_id(&mut contract, None), 3);
+        assert_eq!(contract.next_creative_id, 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "Creative already exists")]
+    fn explicit_id_above_the_counter_is_taken_once() {
+        let mut contract = setup();
+        make_creative_with_id(&mut contract, Some(100));
+        make_creative_with_id(&mut contract, Some(100));
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Creative is used by presentation 1 which is pending")]
+    fn delete_creative_with_open_presentation() {
+        let mut contract = setup();
+        let creative = make_creative(&mut contract, "ipfs://v1");
+        let adspot = contract.make_adspot(None, 1, "Banner".to_string(), None, None, None, None, None, None, None);
+        contract.do_agreement(None, adspot.adspot_id, creative.creative_id, 100, 200, None);
+
+        contract.delete_creative(creative.creative_id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Creative is archived")]
+    fn archived_creative_cannot_be_booked() {
+        let mut contract = setup();
+        let creative = make_creative(&mut contract, "ipfs://v1");
+        let adspot = contract.make_adspot(None, 1, "Banner".to_string(), None, None, None, None, None, None, None);
+        assert!(contract.archive_creative(creative.creative_id).archived);
+
+        contract.do_agreement(None, adspot.adspot_id, creative.creative_id, 100, 200, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Content hash must be 32 bytes")]
+    fn content_hash_must_be_sha256_sized() {
+        let mut contract = setup();
+        contract.make_creative("Banner".to_string(), "ipfs://v1".to_string(), Some(Base64VecU8(vec![1; 4])), None, None, None, None);
+    }
+}
@@ -0,0 +1,318 @@
+use crate::*;
+use std::ops::Range;
+
+/// Page size of the paginated views when no `limit` is given
+pub const DEFAULT_PAGE_LIMIT: u64 = 50;
+/// Larger limits are capped so a page always fits in the view gas limit
+pub const MAX_PAGE_LIMIT: u64 = 100;
+
+/// Ids of the records that belong to each key
+pub type IdIndex<K> = LookupMap<K, UnorderedSet<u64>>;
+/// Ids of the records that belong to each account
+pub type IdsPerAccount = IdIndex<AccountId>;
+
+/// When a presentation shows, relative to the current block time
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Timeframe {
+    Past,
+    Active,
+    Upcoming,
+}
+
+impl Timeframe {
+    pub fn contains(&self, presentation: &Presentation, time: Timestamp) -> bool {
+        match self {
+            Timeframe::Past => presentation.end_time <= time,
+            Timeframe::Active => presentation.start_time <= time && time < presentation.end_time,
+            Timeframe::Upcoming => time < presentation.start_time,
+        }
+    }
+}
+
+/// Paginated views over the collections, in insertion order. Deleting a creative moves the last
+/// creative, globally and of its owner, into the freed position, so a client paging through
+/// creatives while one is deleted can miss or repeat the moved one.
+#[near_bindgen]
+impl MetaAdsContract {
+
+    pub fn fetch_adspots_count(&self) -> U128 {
+        U128(self.ad_spots.len() as u128)
+    }
+
+    pub fn fetch_adspots(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<AdSpot> {
+        let values = self.ad_spots.values_as_vector();
+        page_range(values.len(), from_index, limit)
+            .map(|index| values.get(index).unwrap())
+            .collect()
+    }
+
+    pub fn fetch_creatives_count(&self) -> U128 {
+        U128(self.creatives.len() as u128)
+    }
+
+    pub fn fetch_creatives(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Creative> {
+        let values = self.creatives.values_as_vector();
+        page_range(values.len(), from_index, limit)
+            .map(|index| values.get(index).unwrap())
+            .collect()
+    }
+
+    pub fn fetch_presentations_count(&self) -> U128 {
+        U128(self.presentations.len() as u128)
+    }
+
+    pub fn fetch_presentations(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Presentation> {
+        let values = self.presentations.values_as_vector();
+        page_range(values.len(), from_index, limit)
+            .map(|index| values.get(index).unwrap())
+            .collect()
+    }
+
+    pub fn fetch_adspots_count_for_owner(&self, account_id: AccountId) -> U128 {
+        count_in_index(&self.adspots_per_owner, &account_id)
+    }
+
+    pub fn fetch_adspots_for_owner(&self, account_id: AccountId, from_index: Option<U128>, limit: Option<u64>) -> Vec<AdSpot> {
+        page_of_index(&self.adspots_per_owner, &account_id, from_index, limit)
+            .into_iter()
+            .map(|adspot_id| self.ad_spots.get(&adspot_id).unwrap())
+            .collect()
+    }
+
+    pub fn fetch_creatives_count_for_owner(&self, account_id: AccountId) -> U128 {
+        count_in_index(&self.creatives_per_owner, &account_id)
+    }
+
+    pub fn fetch_creatives_for_owner(&self, account_id: AccountId, from_index: Option<U128>, limit: Option<u64>) -> Vec<Creative> {
+        page_of_index(&self.creatives_per_owner, &account_id, from_index, limit)
+            .into_iter()
+            .map(|creative_id| self.creatives.get(&creative_id).unwrap())
+            .collect()
+    }
+
+    pub fn fetch_presentations_count_for_advertiser(&self, account_id: AccountId) -> U128 {
+        count_in_index(&self.presentations_per_advertiser, &account_id)
+    }
+
+    pub fn fetch_presentations_for_advertiser(&self, account_id: AccountId, from_index: Option<U128>, limit: Option<u64>) -> Vec<Presentation> {
+        page_of_index(&self.presentations_per_advertiser, &account_id, from_index, limit)
+            .into_iter()
+            .map(|playback_id| self.presentations.get(&playback_id).unwrap())
+            .collect()
+    }
+
+    pub fn fetch_presentations_count_for_publisher(&self, account_id: AccountId) -> U128 {
+        count_in_index(&self.presentations_per_publisher, &account_id)
+    }
+
+    pub fn fetch_presentations_for_publisher(&self, account_id: AccountId, from_index: Option<U128>, limit: Option<u64>) -> Vec<Presentation> {
+        page_of_index(&self.presentations_per_publisher, &account_id, from_index, limit)
+            .into_iter()
+            .map(|playback_id| self.presentations.get(&playback_id).unwrap())
+            .collect()
+    }
+
+    pub fn fetch_presentations_count_for_adspot(&self, adspot_id: u64) -> U128 {
+        count_in_index(&self.presentations_per_adspot, &adspot_id)
+    }
+
+    /// Presentations booked on the Ad Spot that pass the filters. `from_index` and `limit` apply to
+    /// all its presentations, so a page can hold fewer than `limit` of them. The next page starts
+    /// at `from_index + limit` until it reaches `fetch_presentations_count_for_adspot`.
+    pub fn fetch_presentations_for_adspot(
+        &self,
+        adspot_id: u64,
+        status: Option<PresentationStatus>,
+        timeframe: Option<Timeframe>,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Presentation> {
+        self.internal_filter_presentations(&self.presentations_per_adspot, &adspot_id, status, timeframe, from_index, limit)
+    }
+
+    pub fn fetch_presentations_count_for_creative(&self, creative_id: u64) -> U128 {
+        count_in_index(&self.presentations_per_creative, &creative_id)
+    }
+
+    /// Presentations of the creative that pass the filters, paginated like
+    /// `fetch_presentations_for_adspot` over all presentations of the creative.
+    pub fn fetch_presentations_for_creative(
+        &self,
+        creative_id: u64,
+        status: Option<PresentationStatus>,
+        timeframe: Option<Timeframe>,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Presentation> {
+        self.internal_filter_presentations(&self.presentations_per_creative, &creative_id, status, timeframe, from_index, limit)
+    }
+}
+
+impl MetaAdsContract {
+    pub(crate) fn internal_index_adspot(&mut self, ad_spot: &AdSpot) {
+        add_to_index(&mut self.adspots_per_owner, &ad_spot.owner_account_id, ad_spot.adspot_id, || {
+            StorageKey::AdSpotsPerOwnerInner { account_id_hash: hash_account_id(&ad_spot.owner_account_id) }
+        });
+    }
+
+    pub(crate) fn internal_index_creative(&mut self, creative: &Creative) {
+        add_to_index(&mut self.creatives_per_owner, &creative.owner_account_id, creative.creative_id, || {
+            StorageKey::CreativesPerOwnerInner { account_id_hash: hash_account_id(&creative.owner_account_id) }
+        });
+    }
+
+    pub(crate) fn internal_unindex_creative(&mut self, creative: &Creative) {
+        remove_from_index(&mut self.creatives_per_owner, &creative.owner_account_id, creative.creative_id);
+    }
+
+    pub(crate) fn internal_index_presentation(&mut self, presentation: &Presentation) {
+        add_to_index(
+            &mut self.presentations_per_advertiser,
+            &presentation.advertiser_account_id,
+            presentation.playback_id,
+            || StorageKey::PresentationsPerAdvertiserInner {
+                account_id_hash: hash_account_id(&presentation.advertiser_account_id),
+            },
+        );
+        add_to_index(
+            &mut self.presentations_per_publisher,
+            &presentation.publisher_account_id,
+            presentation.playback_id,
+            || StorageKey::PresentationsPerPublisherInner {
+                account_id_hash: hash_account_id(&presentation.publisher_account_id),
+            },
+        );
+        add_to_index(&mut self.presentations_per_adspot, &presentation.adspot_id, presentation.playback_id, || {
+            StorageKey::PresentationsPerAdSpotInner { adspot_id: presentation.adspot_id }
+        });
+        add_to_index(&mut self.presentations_per_creative, &presentation.creative_id, presentation.playback_id, || {
+            StorageKey::PresentationsPerCreativeInner { creative_id: presentation.creative_id }
+        });
+    }
+
+    fn internal_filter_presentations<K: BorshSerialize + BorshDeserialize>(
+        &self,
+        index: &IdIndex<K>,
+        key: &K,
+        status: Option<PresentationStatus>,
+        timeframe: Option<Timeframe>,
+        from_index: Option<U128>,
+        limit: Option<u64>,
+    ) -> Vec<Presentation> {
+        let time: u64 = env::block_timestamp() / 1000000000;
+
+        page_of_index(index, key, from_index, limit)
+            .into_iter()
+            .map(|playback_id| self.presentations.get(&playback_id).unwrap())
+            .filter(|presentation| status.is_none_or(|status| presentation.status == status))
+            .filter(|presentation| timeframe.is_none_or(|timeframe| timeframe.contains(presentation, time)))
+            .collect()
+    }
+}
+
+pub(crate) fn hash_account_id(account_id: &AccountId) -> CryptoHash {
+    let mut hash = CryptoHash::default();
+    hash.copy_from_slice(&env::sha256(account_id.as_bytes()));
+    hash
+}
+
+/// Adds `id` to the set of `key`, creating the set under the prefix from `inner_key`
+fn add_to_index<K: BorshSerialize + BorshDeserialize>(
+    index: &mut IdIndex<K>,
+    key: &K,
+    id: u64,
+    inner_key: impl FnOnce() -> StorageKey,
+) {
+    let mut ids = index
+        .get(key)
+        .unwrap_or_else(|| UnorderedSet::new(inner_key().try_to_vec().unwrap()));
+    ids.insert(&id);
+    index.insert(key, &ids);
+}
+
+/// Removes `id` from the set of `key`, and the set once it is empty
+fn remove_from_index<K: BorshSerialize + BorshDeserialize>(index: &mut IdIndex<K>, key: &K, id: u64) {
+    if let Some(mut ids) = index.get(key) {
+        ids.remove(&id);
+        if ids.is_empty() {
+            index.remove(key);
+        } else {
+            index.insert(key, &ids);
+        }
+    }
+}
+
+fn count_in_index<K: BorshSerialize + BorshDeserialize>(index: &IdIndex<K>, key: &K) -> U128 {
+    U128(index.get(key).map(|ids| ids.len() as u128).unwrap_or(0))
+}
+
+fn page_of_index<K: BorshSerialize + BorshDeserialize>(
+    index: &IdIndex<K>,
+    key: &K,
+    from_index: Option<U128>,
+    limit: Option<u64>,
+) -> Vec<u64> {
+    match index.get(key) {
+        Some(ids) => {
+            let ids = ids.as_vector();
+            page_range(ids.len(), from_index, limit).map(|index| ids.get(index).unwrap()).collect()
+        }
+        None => vec![],
+    }
+}
+
+/// Indexes of the page starting at `from_index` in a collection of `len` elements
+pub(crate) fn page_range(len: u64, from_index: Option<U128>, limit: Option<u64>) -> Range<u64> {
+    let start = u128::from(from_index.unwrap_or(U128(0))).min(u128::from(len)) as u64;
+    let limit = limit.unwrap_or(DEFAULT_PAGE_LIMIT).min(MAX_PAGE_LIMIT);
+    start..len.min(start.saturating_add(limit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn page_range_defaults_and_caps() {
+        assert_eq!(page_range(120, None, None), 0..DEFAULT_PAGE_LIMIT);
+        assert_eq!(page_range(120, Some(U128(100)), None), 100..120);
+        assert_eq!(page_range(120, Some(U128(10)), Some(1_000)), 10..10 + MAX_PAGE_LIMIT);
+        assert_eq!(page_range(120, Some(U128(10)), Some(0)), 10..10);
+    }
+
+    #[test]
+    fn page_range_past_the_end() {
+        assert_eq!(page_range(5, Some(U128(7)), Some(3)), 5..5);
+        assert_eq!(page_range(0, None, None), 0..0);
+        assert!(page_range(5, Some(U128(u128::MAX)), None).is_empty());
+        assert_eq!(page_range(u64::MAX, Some(U128(u128::from(u64::MAX - 1))), None), u64::MAX - 1..u64::MAX);
+    }
+
+    #[test]
+    fn filtered_pages_step_over_the_whole_index() {
+        set_caller("publisher.near", NEAR, 0);
+        let mut contract = new_contract();
+        let adspot = contract.make_adspot(None, 1, "Banner".to_string(), None, None, None, None, None, None, None);
+        set_caller("advertiser.near", NEAR, 0);
+        let creative = make_creative(&mut contract, "ipfs://v1");
+        for start_time in [100, 200, 300].iter() {
+            contract.do_agreement(None, adspot.adspot_id, creative.creative_id, *start_time, start_time + 100, None);
+        }
+        set_caller("advertiser.near", 0, 0);
+        contract.cancel_presentation(1);
+
+        let pending = |from_index: u128| {
+            contract
+                .fetch_presentations_for_adspot(adspot.adspot_id, Some(PresentationStatus::Pending), None, Some(U128(from_index)), Some(2))
+                .iter()
+                .map(|presentation| presentation.playback_id)
+                .collect::<Vec<u64>>()
+        };
+        assert_eq!(pending(0), vec![2]);
+        assert_eq!(pending(2), vec![3]);
+        assert!(pending(4).is_empty());
+    }
+}
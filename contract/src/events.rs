@@ -0,0 +1,216 @@
+use crate::*;
+
+/// Name of the NEP-297 standard of the events emitted by the contract
+pub const EVENT_STANDARD: &str = "metaads";
+pub const EVENT_VERSION: &str = "1.0.0";
+
+/// The data of an EventLog. Records are logged whole, so an indexer can rebuild
+/// the contract state from the events alone.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+#[serde(crate = "near_sdk::serde")]
+#[non_exhaustive]
+pub enum EventLogVariant {
+    AdspotCreated(Vec<AdSpot>),
+    AdspotUpdated(Vec<AdSpot>),
+    CreativeCreated(Vec<Creative>),
+    CreativeUpdated(Vec<Creative>),
+    CreativeApproved(Vec<CreativeModerationLog>),
+    CreativeRejected(Vec<CreativeModerationLog>),
+    CreativeDeleted(Vec<CreativeDeletedLog>),
+    PresentationSigned(Vec<Presentation>),
+    PresentationStatusChanged(Vec<PresentationStatusLog>),
+    PresentationCancelled(Vec<PresentationCancelledLog>),
+    PresentationSettled(Vec<PresentationSettledLog>),
+    FeeChanged(Vec<FeeChangedLog>),
+    FeesWithdrawn(Vec<BalanceLog>),
+    ClaimableAdded(Vec<BalanceLog>),
+    ClaimableWithdrawn(Vec<BalanceLog>),
+    StorageDeposited(Vec<BalanceLog>),
+    StorageWithdrawn(Vec<BalanceLog>),
+    RoleGranted(Vec<RoleLog>),
+    RoleRevoked(Vec<RoleLog>),
+    OwnershipTransferStarted(Vec<OwnershipLog>),
+    OwnershipTransferred(Vec<OwnershipLog>),
+}
+
+/// NEP-297 event, logged as `EVENT_JSON:{"standard":"metaads","version":"1.0.0","event":...,"data":[...]}`
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct EventLog {
+    pub standard: String,
+    pub version: String,
+
+    // `flatten` to not have "event": {<EventLogVariant>} in the JSON, just have the contents of {<EventLogVariant>}.
+    #[serde(flatten)]
+    pub event: EventLogVariant,
+}
+
+impl EventLog {
+    pub fn new(event: EventLogVariant) -> Self {
+        Self {
+            standard: EVENT_STANDARD.to_string(),
+            version: EVENT_VERSION.to_string(),
+            event,
+        }
+    }
+
+    pub fn emit(&self) {
+        env::log(self.to_string().as_bytes());
+    }
+}
+
+impl fmt::Display for EventLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_fmt(format_args!(
+            "EVENT_JSON:{}",
+            &near_sdk::serde_json::to_string(self).map_err(|_| fmt::Error)?
+        ))
+    }
+}
+
+/// A moderator decision on `version` of the creative
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreativeModerationLog {
+    pub creative_id: u64,
+    pub version: u32,
+    pub moderator_id: AccountId,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreativeDeletedLog {
+    pub creative_id: u64,
+    pub owner_id: AccountId,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PresentationStatusLog {
+    pub playback_id: u64,
+    pub old_status: PresentationStatus,
+    pub new_status: PresentationStatus,
+}
+
+/// The advertiser cancelled before the start time. `penalty` went to the publisher.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PresentationCancelledLog {
+    pub playback_id: u64,
+    pub advertiser_id: AccountId,
+    pub refund: U128,
+    pub penalty: U128,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_id: Option<AccountId>,
+}
+
+/// The publisher payout resolved. When it was not `delivered` the amount became claimable.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct PresentationSettledLog {
+    pub playback_id: u64,
+    pub publisher_id: AccountId,
+    pub amount: U128,
+    pub fee: U128,
+    pub delivered: bool,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_id: Option<AccountId>,
+}
+
+/// The platform fee when neither `adspot_id` nor `account_id` is set, otherwise an override.
+/// A `fee_bps` of null removes the override.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FeeChangedLog {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adspot_id: Option<u64>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<AccountId>,
+
+    pub fee_bps: Option<u16>,
+}
+
+/// An amount of NEAR, or of `token_id`, credited or paid to `account_id`
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BalanceLog {
+    pub account_id: AccountId,
+    pub amount: U128,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_id: Option<AccountId>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RoleLog {
+    pub account_id: AccountId,
+    pub role: Role,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct OwnershipLog {
+    pub old_owner_id: AccountId,
+    pub new_owner_id: AccountId,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nep_format_status_changed() {
+        let expected = r#"EVENT_JSON:{"standard":"metaads","version":"1.0.0","event":"presentation_status_changed","data":[{"playback_id":7,"old_status":"pending","new_status":"signed"}]}"#;
+        let log = EventLog::new(EventLogVariant::PresentationStatusChanged(vec![PresentationStatusLog {
+            playback_id: 7,
+            old_status: PresentationStatus::Pending,
+            new_status: PresentationStatus::Signed,
+        }]));
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_creative_rejected() {
+        let expected = r#"EVENT_JSON:{"standard":"metaads","version":"1.0.0","event":"creative_rejected","data":[{"creative_id":4,"version":2,"moderator_id":"moderator.near","reason":"Misleading claims"}]}"#;
+        let log = EventLog::new(EventLogVariant::CreativeRejected(vec![CreativeModerationLog {
+            creative_id: 4,
+            version: 2,
+            moderator_id: "moderator.near".to_string(),
+            reason: Some("Misleading claims".to_string()),
+        }]));
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_fee_changed() {
+        let expected = r#"EVENT_JSON:{"standard":"metaads","version":"1.0.0","event":"fee_changed","data":[{"fee_bps":500},{"adspot_id":3,"fee_bps":null}]}"#;
+        let log = EventLog::new(EventLogVariant::FeeChanged(vec![
+            FeeChangedLog { adspot_id: None, account_id: None, fee_bps: Some(500) },
+            FeeChangedLog { adspot_id: Some(3), account_id: None, fee_bps: None },
+        ]));
+        assert_eq!(expected, log.to_string());
+    }
+
+    #[test]
+    fn nep_format_settled_all_fields() {
+        let expected = r#"EVENT_JSON:{"standard":"metaads","version":"1.0.0","event":"presentation_settled","data":[{"playback_id":1,"publisher_id":"publisher.near","amount":"900","fee":"100","delivered":false,"token_id":"usdc.near"}]}"#;
+        let log = EventLog::new(EventLogVariant::PresentationSettled(vec![PresentationSettledLog {
+            playback_id: 1,
+            publisher_id: "publisher.near".to_string(),
+            amount: U128(900),
+            fee: U128(100),
+            delivered: false,
+            token_id: Some("usdc.near".to_string()),
+        }]));
+        assert_eq!(expected, log.to_string());
+    }
+}
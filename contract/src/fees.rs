@@ -0,0 +1,170 @@
+use crate::*;
+
+/// 100% in basis points
+pub const MAX_FEE_BPS: u16 = 10_000;
+/// The 10% the contract used to keep from every deposit
+pub const DEFAULT_PLATFORM_FEE_BPS: u16 = 1_000;
+
+pub(crate) fn fee_from_bps(amount: Balance, fee_bps: u16) -> Balance {
+    amount * Balance::from(fee_bps) / Balance::from(MAX_FEE_BPS)
+}
+
+fn assert_valid_fee(fee_bps: u16) {
+    assert!(fee_bps <= MAX_FEE_BPS, "Abort. Fee must be between 0 and {} basis points", MAX_FEE_BPS);
+}
+
+fn emit_fee_changed(adspot_id: Option<u64>, account_id: Option<AccountId>, fee_bps: Option<u16>) {
+    EventLog::new(EventLogVariant::FeeChanged(vec![FeeChangedLog { adspot_id, account_id, fee_bps }])).emit();
+}
+
+#[near_bindgen]
+impl MetaAdsContract {
+
+    #[payable]
+    pub fn set_platform_fee(&mut self, fee_bps: u16) {
+        assert_one_yocto();
+        self.assert_role(Role::FeeManager);
+        assert_valid_fee(fee_bps);
+        log!("Platform fee changed from {} to {} basis points", self.platform_fee_bps, fee_bps);
+        self.platform_fee_bps = fee_bps;
+        emit_fee_changed(None, None, Some(fee_bps));
+    }
+
+    /// Overrides the platform fee for a single Ad Spot. `None` removes the override.
+    #[payable]
+    pub fn set_adspot_fee(&mut self, adspot_id: u64, fee_bps: Option<u16>) {
+        assert_one_yocto();
+        self.assert_role(Role::FeeManager);
+        assert!(self.ad_spots.get(&adspot_id).is_some(), "Ad Spot not found");
+        match fee_bps {
+            Some(fee_bps) => {
+                assert_valid_fee(fee_bps);
+                self.adspot_fees.insert(&adspot_id, &fee_bps);
+            }
+            None => {
+                self.adspot_fees.remove(&adspot_id);
+            }
+        }
+        emit_fee_changed(Some(adspot_id), None, fee_bps);
+    }
+
+    /// Overrides the platform fee for every Ad Spot of a publisher. `None` removes the override.
+    #[payable]
+    pub fn set_publisher_fee(&mut self, account_id: AccountId, fee_bps: Option<u16>) {
+        assert_one_yocto();
+        self.assert_role(Role::FeeManager);
+        assert!(env::is_valid_account_id(account_id.as_bytes()), "Abort. Invalid account id");
+        match fee_bps {
+            Some(fee_bps) => {
+                assert_valid_fee(fee_bps);
+                self.publisher_fees.insert(&account_id, &fee_bps);
+            }
+            None => {
+                self.publisher_fees.remove(&account_id);
+            }
+        }
+        emit_fee_changed(None, Some(account_id), fee_bps);
+    }
+
+    pub fn fetch_platform_fee(&self) -> u16 {
+        self.platform_fee_bps
+    }
+
+    /// The fee in basis points a new presentation on the Ad Spot would be signed with
+    pub fn fetch_adspot_fee(&self, adspot_id: u64) -> u16 {
+        let adspot = self.ad_spots.get(&adspot_id).expect("AdSpot not found");
+        self.internal_fee_for_adspot(&adspot)
+    }
+}
+
+impl MetaAdsContract {
+    /// Ad Spot override first, then publisher override, then the platform fee
+    pub(crate) fn internal_fee_for_adspot(&self, adspot: &AdSpot) -> u16 {
+        self.adspot_fees
+            .get(&adspot.adspot_id)
+            .or_else(|| self.publisher_fees.get(&adspot.owner_account_id))
+            .unwrap_or(self.platform_fee_bps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    /// An Ad Spot of publisher.near costing `SPOT_NEAR` and a creative of advertiser.near
+    fn setup() -> (MetaAdsContract, u64, u64) {
+        set_caller("publisher.near", NEAR, 0);
+        let mut contract = new_contract();
+        let adspot = contract.make_adspot(None, 1, "Banner".to_string(), None, None, None, None, None, None, None);
+
+        set_caller("advertiser.near", NEAR, 0);
+        let creative = make_creative(&mut contract, "ipfs://v1");
+        (contract, adspot.adspot_id, creative.creative_id)
+    }
+
+    #[test]
+    fn adspot_fee_beats_publisher_fee_beats_platform_fee() {
+        let (mut contract, adspot_id, _) = setup();
+        assert_eq!(contract.fetch_adspot_fee(adspot_id), DEFAULT_PLATFORM_FEE_BPS);
+
+        set_caller("owner.near", 1, 0);
+        contract.set_platform_fee(500);
+        assert_eq!(contract.fetch_adspot_fee(adspot_id), 500);
+        contract.set_publisher_fee("publisher.near".to_string(), Some(300));
+        assert_eq!(contract.fetch_adspot_fee(adspot_id), 300);
+        contract.set_adspot_fee(adspot_id, Some(200));
+        assert_eq!(contract.fetch_adspot_fee(adspot_id), 200);
+
+        contract.set_adspot_fee(adspot_id, None);
+        assert_eq!(contract.fetch_adspot_fee(adspot_id), 300);
+        contract.set_publisher_fee("publisher.near".to_string(), None);
+        assert_eq!(contract.fetch_adspot_fee(adspot_id), 500);
+    }
+
+    #[test]
+    fn presentations_keep_the_fee_they_were_signed_with() {
+        let (mut contract, adspot_id, creative_id) = setup();
+        contract.do_agreement(None, adspot_id, creative_id, 100, 200, None);
+
+        set_caller("owner.near", 1, 0);
+        contract.set_platform_fee(0);
+
+        let presentation = contract.fetch_presentation_by_id(1);
+        assert_eq!(presentation.fee_bps, DEFAULT_PLATFORM_FEE_BPS);
+        assert_eq!(presentation.entertainment_fee, SPOT_NEAR / 10);
+
+        set_caller("publisher.near", 0, 10);
+        contract.accept_presentation(1);
+        set_caller("publisher.near", 0, 300);
+        contract.transfer_funds(1);
+        assert_eq!(contract.fetch_treasury(None).fees_earned, SPOT_NEAR / 10);
+    }
+
+    #[test]
+    fn fee_managers_set_fees() {
+        let (mut contract, _, _) = setup();
+        set_caller("owner.near", 1, 0);
+        contract.grant_role("fees.near".to_string(), Role::FeeManager);
+
+        set_caller("fees.near", 1, 0);
+        contract.set_publisher_fee("publisher.near".to_string(), Some(MAX_FEE_BPS));
+        assert_eq!(contract.publisher_fees.get(&"publisher.near".to_string()), Some(MAX_FEE_BPS));
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Requires the FeeManager role")]
+    fn others_cannot_set_fees() {
+        let (mut contract, _, _) = setup();
+        set_caller("publisher.near", 1, 0);
+        contract.set_platform_fee(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Fee must be between 0 and 10000 basis points")]
+    fn fee_above_one_hundred_percent() {
+        let (mut contract, adspot_id, _) = setup();
+        set_caller("owner.near", 1, 0);
+        contract.set_adspot_fee(adspot_id, Some(MAX_FEE_BPS + 1));
+    }
+}
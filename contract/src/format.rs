@@ -0,0 +1,168 @@
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum FormatKind {
+    Image,
+    Video,
+    Html5,
+    Text,
+}
+
+impl fmt::Display for FormatKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self {
+            FormatKind::Image => "image",
+            FormatKind::Video => "video",
+            FormatKind::Html5 => "html5",
+            FormatKind::Text => "text",
+        };
+        f.write_str(kind)
+    }
+}
+
+/// The format of a creative. Dimensions are in pixels.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct CreativeFormat {
+    pub kind: FormatKind,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// Required for videos
+    pub duration_secs: Option<u32>,
+    pub size_bytes: Option<u64>,
+}
+
+/// A format an Ad Spot accepts. Unset fields accept any value.
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct AcceptedFormat {
+    pub kind: FormatKind,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub max_duration_secs: Option<u32>,
+    pub max_bytes: Option<u64>,
+}
+
+impl AcceptedFormat {
+    /// Why `format` does not fit, `None` when it does
+    pub fn mismatch(&self, format: &CreativeFormat) -> Option<String> {
+        if self.kind != format.kind {
+            return Some(format!("{} instead of {}", format.kind, self.kind));
+        }
+        if (self.width.is_some() && self.width != format.width) || (self.height.is_some() && self.height != format.height) {
+            return Some(format!(
+                "{} of {} instead of {}",
+                self.kind,
+                dimensions(format.width, format.height),
+                dimensions(self.width, self.height)
+            ));
+        }
+        if let Some(max_duration_secs) = self.max_duration_secs {
+            match format.duration_secs {
+                Some(duration_secs) if duration_secs <= max_duration_secs => {}
+                _ => return Some(format!("{} longer than {} seconds", self.kind, max_duration_secs)),
+            }
+        }
+        if let Some(max_bytes) = self.max_bytes {
+            match format.size_bytes {
+                Some(size_bytes) if size_bytes <= max_bytes => {}
+                _ => return Some(format!("{} larger than {} bytes", self.kind, max_bytes)),
+            }
+        }
+        None
+    }
+}
+
+fn dimensions(width: Option<u32>, height: Option<u32>) -> String {
+    let side = |side: Option<u32>| side.map_or("any".to_string(), |side| side.to_string());
+    format!("{}x{}", side(width), side(height))
+}
+
+pub(crate) fn assert_valid_creative_format(format: &CreativeFormat) {
+    assert!(
+        format.width != Some(0) && format.height != Some(0),
+        "Abort. Creative dimensions must be greater than 0"
+    );
+    if format.kind == FormatKind::Video {
+        assert!(
+            format.duration_secs.unwrap_or(0) > 0,
+            "Abort. Video creatives must declare their duration"
+        );
+    }
+}
+
+pub(crate) fn assert_valid_accepted_formats(accepted_formats: &[AcceptedFormat]) {
+    for accepted in accepted_formats.iter() {
+        assert!(
+            accepted.width != Some(0) && accepted.height != Some(0),
+            "Abort. Accepted dimensions must be greater than 0"
+        );
+    }
+}
+
+/// Ad Spots without accepted formats take any creative. The others take creatives that
+/// declare a format matching one of them.
+pub(crate) fn assert_creative_format_accepted(creative: &Creative, adspot: &AdSpot) {
+    if adspot.accepted_formats.is_empty() {
+        return;
+    }
+    let format = creative.format.as_ref().unwrap_or_else(|| {
+        env::panic(b"Abort. Ad Spot accepts only creatives that declare their format")
+    });
+
+    let mismatches: Vec<String> = adspot
+        .accepted_formats
+        .iter()
+        .map(|accepted| accepted.mismatch(format))
+        .collect::<Option<Vec<String>>>()
+        .unwrap_or_default();
+    if !mismatches.is_empty() {
+        env::panic(
+            format!(
+                "Abort. Creative format is not accepted by the Ad Spot: {}",
+                mismatches.join("; ")
+            )
+            .as_bytes(),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn banner(width: u32, height: u32) -> CreativeFormat {
+        CreativeFormat { kind: FormatKind::Image, width: Some(width), height: Some(height), duration_secs: None, size_bytes: Some(40_000) }
+    }
+
+    fn accepts_banner() -> AcceptedFormat {
+        AcceptedFormat { kind: FormatKind::Image, width: Some(728), height: Some(90), max_duration_secs: None, max_bytes: Some(150_000) }
+    }
+
+    #[test]
+    fn matching_format() {
+        assert_eq!(accepts_banner().mismatch(&banner(728, 90)), None);
+
+        let any_size = AcceptedFormat { width: None, height: None, ..accepts_banner() };
+        assert_eq!(any_size.mismatch(&banner(300, 250)), None);
+    }
+
+    #[test]
+    fn mismatching_formats() {
+        assert_eq!(
+            accepts_banner().mismatch(&banner(300, 250)),
+            Some("image of 300x250 instead of 728x90".to_string())
+        );
+
+        let video = CreativeFormat { kind: FormatKind::Video, width: None, height: None, duration_secs: Some(30), size_bytes: None };
+        assert_eq!(accepts_banner().mismatch(&video), Some("video instead of image".to_string()));
+
+        let short_videos = AcceptedFormat { kind: FormatKind::Video, width: None, height: None, max_duration_secs: Some(15), max_bytes: Some(1_000) };
+        assert_eq!(short_videos.mismatch(&video), Some("video longer than 15 seconds".to_string()));
+
+        let heavy = CreativeFormat { size_bytes: Some(200_000), ..banner(728, 90) };
+        assert_eq!(accepts_banner().mismatch(&heavy), Some("image larger than 150000 bytes".to_string()));
+    }
+}
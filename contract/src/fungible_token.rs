@@ -0,0 +1,189 @@
+use crate::*;
+
+const GAS_FOR_FT_TRANSFER: Gas = 10_000_000_000_000;
+const ONE_YOCTO: Balance = 1;
+
+#[ext_contract(ext_ft)]
+pub trait FungibleToken {
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>);
+}
+
+/// NEP-141 receiver. Transferring tokens with `ft_transfer_call` books an Ad Spot that
+/// accepts the token, `msg` is a JSON `BookingMessage`. The tokens beyond the price are returned.
+#[near_bindgen]
+impl MetaAdsContract {
+
+    pub fn ft_on_transfer(&mut self, sender_id: ValidAccountId, amount: U128, msg: String) -> PromiseOrValue<U128> {
+        let token_id = env::predecessor_account_id();
+        let booking: BookingMessage = near_sdk::serde_json::from_str(&msg)
+            .unwrap_or_else(|_| env::panic(b"Abort. The message is not a valid booking"));
+
+        if let Some(nft) = self.internal_creative_nft(booking.creative_id) {
+            return PromiseOrValue::Promise(self.internal_book_with_nft(&nft, sender_id.into(), booking, Some(token_id), amount.0));
+        }
+
+        let (presentation, unused) = self.internal_do_agreement(sender_id.into(), booking, Some(token_id), amount.0);
+        log!(
+            "Presentation {} paid with {} of {}",
+            presentation.playback_id,
+            presentation.advertiser_cost,
+            env::predecessor_account_id()
+        );
+
+        PromiseOrValue::Value(U128(unused))
+    }
+
+    #[private]
+    pub fn on_payout(&mut self, account_id: AccountId, amount: Balance, token_id: Option<AccountId>) -> bool {
+        assert_eq!(env::promise_results_count(), 1, "Abort. Expected one promise result");
+
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => true,
+            _ => {
+                log!(
+                    "Transfer of {} to {} failed. The amount can be claimed with withdraw_claimable",
+                    amount, account_id
+                );
+                self.internal_add_claimable(&account_id, amount, &token_id);
+                false
+            }
+        }
+    }
+}
+
+impl MetaAdsContract {
+    /// Sends `amount` of NEAR, or of `token_id` through `ft_transfer`
+    pub(crate) fn internal_transfer(&self, receiver_id: &AccountId, amount: Balance, token_id: &Option<AccountId>) -> Promise {
+        match token_id {
+            Some(token_id) => ext_ft::ft_transfer(
+                receiver_id.clone(),
+                U128(amount),
+                None,
+                token_id,
+                ONE_YOCTO,
+                GAS_FOR_FT_TRANSFER,
+            ),
+            None => Promise::new(receiver_id.clone()).transfer(amount),
+        }
+    }
+
+    /// Like `internal_transfer`, but a failed transfer becomes a claimable balance of the receiver
+    pub(crate) fn internal_payout(&self, receiver_id: &AccountId, amount: Balance, token_id: &Option<AccountId>) -> Promise {
+        self.internal_transfer(receiver_id, amount, token_id).then(ext_self::on_payout(
+            receiver_id.clone(),
+            amount,
+            token_id.clone(),
+            &env::current_account_id(),
+            0,
+            GAS_FOR_RESOLVE_TRANSFER,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::test_utils::get_logs;
+    use std::convert::TryInto;
+
+    const USDC: &str = "usdc.near";
+
+    /// An Ad Spot of publisher.near costing 1000 USDC with a 30% cancellation penalty, and a
+    /// creative of advertiser.near whose storage balance pays for the bookings
+    fn setup() -> (MetaAdsContract, u64, u64) {
+        set_caller("publisher.near", NEAR, 0);
+        let mut contract = new_contract();
+        let mut accepted_tokens = HashMap::new();
+        accepted_tokens.insert(USDC.to_string(), U128(1000));
+        let adspot = contract.make_adspot(None, 1, "Banner".to_string(), None, None, Some(30), None, Some(accepted_tokens), None, None);
+
+        set_caller("advertiser.near", NEAR, 0);
+        let creative = make_creative(&mut contract, "ipfs://v1");
+        contract.storage_deposit(None, None);
+        (contract, adspot.adspot_id, creative.creative_id)
+    }
+
+    /// Books the window from 100 to 200 as advertiser.near
+    fn booking(adspot_id: u64, creative_id: u64) -> String {
+        format!(r#"{{"adspot_id":{},"creative_id":{},"start_time":100,"end_time":200}}"#, adspot_id, creative_id)
+    }
+
+    /// Transfers `amount` of `token_id` from advertiser.near, returns the amount sent back
+    fn pay(contract: &mut MetaAdsContract, token_id: &str, amount: Balance, msg: String) -> Balance {
+        set_caller(token_id, 0, 0);
+        match contract.ft_on_transfer("advertiser.near".to_string().try_into().unwrap(), U128(amount), msg) {
+            PromiseOrValue::Value(unused) => unused.0,
+            _ => panic!("presentation not booked"),
+        }
+    }
+
+    #[test]
+    fn token_booking_returns_the_unused_amount() {
+        let (mut contract, adspot_id, creative_id) = setup();
+
+        assert_eq!(pay(&mut contract, USDC, 1500, booking(adspot_id, creative_id)), 500);
+
+        let presentation = contract.fetch_presentation_by_id(1);
+        assert_eq!(presentation.token_id, Some(USDC.to_string()));
+        assert_eq!(presentation.advertiser_cost, 1000);
+        assert_eq!(presentation.entertainment_fee, 100);
+        assert_eq!(presentation.storage_cost, 0);
+        assert_eq!(contract.fetch_treasury(Some(USDC.to_string())).escrowed, 1000);
+        assert_eq!(contract.fetch_treasury(None).escrowed, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Ad Spot does not accept dai.near")]
+    fn token_the_spot_does_not_accept() {
+        let (mut contract, adspot_id, creative_id) = setup();
+        pay(&mut contract, "dai.near", 1000, booking(adspot_id, creative_id));
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. The message is not a valid booking")]
+    fn message_that_is_not_a_booking() {
+        let (mut contract, _, _) = setup();
+        pay(&mut contract, USDC, 1000, "book it".to_string());
+    }
+
+    #[test]
+    fn settlement_pays_the_publisher_in_the_token() {
+        let (mut contract, adspot_id, creative_id) = setup();
+        pay(&mut contract, USDC, 1000, booking(adspot_id, creative_id));
+        set_caller("publisher.near", 0, 10);
+        contract.accept_presentation(1);
+
+        set_caller("publisher.near", 0, 300);
+        contract.transfer_funds(1);
+        let treasury = contract.fetch_treasury(Some(USDC.to_string()));
+        assert_eq!(treasury.escrowed, 0);
+        assert_eq!(treasury.fees_earned, 100);
+        assert_eq!(contract.fetch_treasury(None).fees_earned, 0);
+
+        resolve_as_contract(PromiseResult::Failed);
+        assert!(!contract.on_transfer_funds(1));
+        assert!(get_logs().iter().any(|log| log.contains(r#""amount":"900","fee":"100","delivered":false,"token_id":"usdc.near""#)));
+        assert_eq!(contract.fetch_claimable_balance("publisher.near".to_string(), Some(USDC.to_string())), 900);
+        assert_eq!(contract.fetch_claimable_balance("publisher.near".to_string(), None), 0);
+        assert_eq!(contract.fetch_treasury(Some(USDC.to_string())).claimable, 900);
+    }
+
+    #[test]
+    fn cancellation_pays_out_in_the_token() {
+        let (mut contract, adspot_id, creative_id) = setup();
+        pay(&mut contract, USDC, 1000, booking(adspot_id, creative_id));
+        set_caller("publisher.near", 0, 10);
+        contract.accept_presentation(1);
+
+        set_caller("advertiser.near", 0, 50);
+        contract.cancel_presentation(1);
+        assert!(get_logs().iter().any(|log| log.contains(r#""refund":"700","penalty":"300","token_id":"usdc.near""#)));
+        assert_eq!(contract.fetch_treasury(Some(USDC.to_string())).escrowed, 0);
+
+        resolve_as_contract(PromiseResult::Failed);
+        assert!(!contract.on_payout("advertiser.near".to_string(), 700, Some(USDC.to_string())));
+        assert_eq!(contract.fetch_claimable_balance("advertiser.near".to_string(), Some(USDC.to_string())), 700);
+        assert_eq!(contract.fetch_treasury(None).claimable, 0);
+    }
+}
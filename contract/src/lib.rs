@@ -1,84 +1,345 @@
+// Contract methods take their JSON arguments one by one
+#![allow(clippy::too_many_arguments)]
+
 // To conserve gas, efficient serialization is achieved through Borsh (http://borsh.io/)
-use near_sdk::{AccountId, Promise, Balance, env, near_bindgen, log, setup_alloc, Timestamp};
-use near_sdk::collections::{UnorderedMap};
+use near_sdk::{AccountId, CryptoHash, Promise, PromiseOrValue, PromiseResult, Balance, Gas, PanicOnDefault, StorageUsage, assert_one_yocto, env, ext_contract, near_bindgen, log, setup_alloc, Timestamp};
+use near_sdk::json_types::{Base64VecU8, ValidAccountId, U128};
+use near_sdk::collections::{LookupMap, LookupSet, TreeMap, UnorderedMap, UnorderedSet};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
 
 // 0.01 NEAR
 const SPOT_NEAR: Balance = 10_000_000_000_000_000_000_000;
+const GAS_FOR_RESOLVE_TRANSFER: Gas = 10_000_000_000_000;
+
+/// Version of the storage layout written by this code, see `migrate`
+pub const STATE_VERSION: u32 = 2;
+const STATE_VERSION_KEY: &[u8] = b"STATE_VERSION";
 
 setup_alloc!();
 
+pub use crate::calendar::*;
 pub use crate::creative::*;
+pub use crate::enumeration::*;
+pub use crate::events::*;
+pub use crate::fees::*;
+pub use crate::format::*;
+pub use crate::fungible_token::*;
+pub use crate::moderation::*;
+pub use crate::nft::*;
 pub use crate::presentation::*;
+pub use crate::pricing::*;
+pub use crate::roles::*;
+pub use crate::settlement::*;
 pub use crate::ad_spot::*;
+pub use crate::storage::*;
+pub use crate::treasury::*;
+use crate::migration::migration_in_progress;
 
 mod ad_spot;
+mod calendar;
 mod creative;
+mod enumeration;
+mod events;
+mod fees;
+mod format;
+mod fungible_token;
+mod migration;
+mod moderation;
+mod nft;
 mod presentation;
+mod pricing;
+mod roles;
+mod settlement;
+mod storage;
+mod treasury;
+#[cfg(test)]
+mod test_utils;
 
 #[derive(BorshSerialize)]
 pub enum StorageKey {
     Creatives,
     Presentations,
     AdSpot,
+    Roles,
+    ClaimableBalances,
+    AdSpotFees,
+    PublisherFees,
+    Bookings,
+    BookingsInner { adspot_id: u64 },
+    StorageAccounts,
+    TokenTreasuries,
+    AdSpotsPerOwner,
+    AdSpotsPerOwnerInner { account_id_hash: CryptoHash },
+    CreativesPerOwner,
+    CreativesPerOwnerInner { account_id_hash: CryptoHash },
+    PresentationsPerAdvertiser,
+    PresentationsPerAdvertiserInner { account_id_hash: CryptoHash },
+    PresentationsPerPublisher,
+    PresentationsPerPublisherInner { account_id_hash: CryptoHash },
+    PresentationsPerAdSpot,
+    PresentationsPerAdSpotInner { adspot_id: u64 },
+    PresentationsPerCreative,
+    PresentationsPerCreativeInner { creative_id: u64 },
+    CreativeVersions,
+    PendingCreatives,
+    OpenPresentations,
+    DeletedCreatives,
+    TakenAdSpotIds,
+    TakenCreativeIds,
+    TakenPlaybackIds,
+    LongestBookings,
 }
 
 #[near_bindgen]
-#[derive(BorshDeserialize, BorshSerialize)]
+#[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct MetaAdsContract {
+    pub owner_id: AccountId,
+    pub pending_owner_id: Option<AccountId>,
+    pub roles: UnorderedMap<AccountId, Vec<Role>>,
     pub creatives: UnorderedMap<u64, Creative>,
     pub presentations: UnorderedMap<u64, Presentation>,
     pub ad_spots: UnorderedMap<u64, AdSpot>,
+    /// Undelivered payouts per (account, token), NEAR when the token is `None`
+    pub claimable_balances: LookupMap<(AccountId, Option<AccountId>), Balance>,
+    pub platform_fee_bps: u16,
+    pub adspot_fees: LookupMap<u64, u16>,
+    pub publisher_fees: LookupMap<AccountId, u16>,
+    pub treasury: Treasury,
+    pub bookings_per_adspot: LookupMap<u64, Bookings>,
+    /// Longest booking ever made per Ad Spot, bounds how far back a booking can reach
+    pub longest_booking_per_adspot: LookupMap<u64, Timestamp>,
+    pub storage_accounts: LookupMap<AccountId, StorageAccount>,
+    pub token_treasuries: LookupMap<AccountId, Treasury>,
+    /// Ids handed out to the next record created without an explicit id
+    pub next_adspot_id: u64,
+    pub next_creative_id: u64,
+    pub next_playback_id: u64,
+    /// Explicit ids above the counters, see `TakenIds`
+    pub taken_adspot_ids: TakenIds,
+    pub taken_creative_ids: TakenIds,
+    pub taken_playback_ids: TakenIds,
+    pub adspots_per_owner: IdsPerAccount,
+    pub creatives_per_owner: IdsPerAccount,
+    pub presentations_per_advertiser: IdsPerAccount,
+    pub presentations_per_publisher: IdsPerAccount,
+    pub presentations_per_adspot: IdIndex<u64>,
+    pub presentations_per_creative: IdIndex<u64>,
+    /// Every version of every creative, keyed by (creative_id, version)
+    pub creative_versions: LookupMap<(u64, u32), CreativeVersion>,
+    /// Creatives waiting for a moderator
+    pub pending_creatives: UnorderedSet<u64>,
+    /// Presentations waiting for settlement, keyed by (end_time, playback_id)
+    pub open_presentations: OpenPresentations,
+    /// Ids of deleted creatives, never reused since their presentations still refer to them
+    pub deleted_creatives: LookupSet<u64>,
+}
+
+/// Settings accepted by `new`
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Config {
+    pub owner_id: AccountId,
+    /// Defaults to `DEFAULT_PLATFORM_FEE_BPS`
+    pub platform_fee_bps: Option<u16>,
+}
+
+#[ext_contract(ext_self)]
+pub trait MetaAdsResolver {
+    fn on_transfer_funds(&mut self, playback_id: u64) -> bool;
+    fn on_withdraw_claimable(&mut self, account_id: AccountId, amount: Balance, token_id: Option<AccountId>) -> bool;
+    fn on_withdraw_fees(&mut self, amount: Balance, receiver_id: AccountId, token_id: Option<AccountId>) -> bool;
+    fn on_payout(&mut self, account_id: AccountId, amount: Balance, token_id: Option<AccountId>) -> bool;
+    fn on_creative_nft_verified(&mut self, creative: Creative, attached: U128) -> PromiseOrValue<Option<Creative>>;
+    fn add_verified_creative(&mut self, creative: Creative, attached: U128) -> Creative;
+    fn on_verified_creative_resolved(&mut self, owner_account_id: AccountId, attached: U128) -> Option<Creative>;
+    fn on_booking_nft_verified(
+        &mut self,
+        advertiser_account_id: AccountId,
+        booking: BookingMessage,
+        amount: U128,
+    ) -> PromiseOrValue<Option<Presentation>>;
+    fn book_verified(&mut self, advertiser_account_id: AccountId, booking: BookingMessage, amount: U128) -> Presentation;
+    fn on_verified_booking_resolved(&mut self, advertiser_account_id: AccountId, amount: U128) -> Option<Presentation>;
+    fn on_token_booking_nft_verified(
+        &mut self,
+        advertiser_account_id: AccountId,
+        booking: BookingMessage,
+        token_id: AccountId,
+        amount: U128,
+    ) -> U128;
+}
+
+#[near_bindgen]
+impl MetaAdsContract {
+    #[init]
+    pub fn new(config: Config) -> Self {
+        assert!(!migration_in_progress(), "Abort. A migration is in progress");
+        write_state_version(STATE_VERSION);
+        Self::from_config(config)
+    }
+
+    pub fn fetch_state_version(&self) -> u32 {
+        read_state_version()
+    }
 }
 
-impl Default for MetaAdsContract {
-    fn default() -> Self {
-        assert!(!env::state_exists(), "The contract is already initialized");
+impl MetaAdsContract {
+    /// Empty collections and the given settings, without touching the stored state version
+    pub(crate) fn from_config(config: Config) -> Self {
+        assert!(env::is_valid_account_id(config.owner_id.as_bytes()), "Abort. Invalid account id");
+        let platform_fee_bps = config.platform_fee_bps.unwrap_or(DEFAULT_PLATFORM_FEE_BPS);
+        assert!(platform_fee_bps <= MAX_FEE_BPS, "Abort. Fee must be between 0 and {} basis points", MAX_FEE_BPS);
+
         Self {
+            owner_id: config.owner_id,
+            pending_owner_id: None,
+            roles: UnorderedMap::new(StorageKey::Roles.try_to_vec().unwrap()),
             creatives: UnorderedMap::new(StorageKey::Creatives.try_to_vec().unwrap()),
             presentations: UnorderedMap::new(StorageKey::Presentations.try_to_vec().unwrap()),
             ad_spots: UnorderedMap::new(StorageKey::AdSpot.try_to_vec().unwrap()),
+            claimable_balances: LookupMap::new(StorageKey::ClaimableBalances.try_to_vec().unwrap()),
+            platform_fee_bps,
+            adspot_fees: LookupMap::new(StorageKey::AdSpotFees.try_to_vec().unwrap()),
+            publisher_fees: LookupMap::new(StorageKey::PublisherFees.try_to_vec().unwrap()),
+            treasury: Treasury::default(),
+            bookings_per_adspot: LookupMap::new(StorageKey::Bookings.try_to_vec().unwrap()),
+            longest_booking_per_adspot: LookupMap::new(StorageKey::LongestBookings.try_to_vec().unwrap()),
+            storage_accounts: LookupMap::new(StorageKey::StorageAccounts.try_to_vec().unwrap()),
+            token_treasuries: LookupMap::new(StorageKey::TokenTreasuries.try_to_vec().unwrap()),
+            next_adspot_id: 1,
+            next_creative_id: 1,
+            next_playback_id: 1,
+            taken_adspot_ids: TreeMap::new(StorageKey::TakenAdSpotIds.try_to_vec().unwrap()),
+            taken_creative_ids: TreeMap::new(StorageKey::TakenCreativeIds.try_to_vec().unwrap()),
+            taken_playback_ids: TreeMap::new(StorageKey::TakenPlaybackIds.try_to_vec().unwrap()),
+            adspots_per_owner: LookupMap::new(StorageKey::AdSpotsPerOwner.try_to_vec().unwrap()),
+            creatives_per_owner: LookupMap::new(StorageKey::CreativesPerOwner.try_to_vec().unwrap()),
+            presentations_per_advertiser: LookupMap::new(StorageKey::PresentationsPerAdvertiser.try_to_vec().unwrap()),
+            presentations_per_publisher: LookupMap::new(StorageKey::PresentationsPerPublisher.try_to_vec().unwrap()),
+            presentations_per_adspot: LookupMap::new(StorageKey::PresentationsPerAdSpot.try_to_vec().unwrap()),
+            presentations_per_creative: LookupMap::new(StorageKey::PresentationsPerCreative.try_to_vec().unwrap()),
+            creative_versions: LookupMap::new(StorageKey::CreativeVersions.try_to_vec().unwrap()),
+            pending_creatives: UnorderedSet::new(StorageKey::PendingCreatives.try_to_vec().unwrap()),
+            open_presentations: TreeMap::new(StorageKey::OpenPresentations.try_to_vec().unwrap()),
+            deleted_creatives: LookupSet::new(StorageKey::DeletedCreatives.try_to_vec().unwrap()),
         }
     }
 }
 
+/// The version lives outside the contract struct so it can be read before knowing the layout.
+/// Contracts deployed before versioning have no version stored and use layout 1.
+pub(crate) fn read_state_version() -> u32 {
+    env::storage_read(STATE_VERSION_KEY)
+        .map(|bytes| u32::try_from_slice(&bytes).expect("Abort. Corrupted state version"))
+        .unwrap_or(1)
+}
 
-#[near_bindgen]
-impl MetaAdsContract {
+pub(crate) fn write_state_version(version: u32) {
+    env::storage_write(STATE_VERSION_KEY, &version.try_to_vec().unwrap());
+}
+
+/// Explicit ids taken above the counter, as ranges of consecutive ids from the first to the last.
+/// The counter never points into a range, it jumps over the whole range once it reaches it.
+pub type TakenIds = TreeMap<u64, u64>;
 
-    #[private]
-    pub fn transfer_funds(&mut self, playback_id: u64) -> bool {
-        
-        assert!(playback_id > 0, "Abort. Presentation Id undefined");
-
-        match self.presentations.get(&playback_id) {
-            Some(mut presentation) => {
-                
-                assert!(presentation.transfered == false, "Abort. Transfer funds");
-
-                let time: u64 = env::block_timestamp() / 1000000000;
-                assert!(presentation.end_time <= time, "Abort. Presentation is active. Show time is not over yet");
-        
-                let total_funds: Balance = presentation.advertiser_cost - presentation.entertainment_fee;
-                let account_id = presentation.publisher_account_id.clone();
-        
-                Promise::new(account_id.clone()).transfer(total_funds);
-                
-                log!("The publisher {} received funds in the amount of {}", account_id.clone(), total_funds);
-
-                presentation.transfered = true;
-                presentation.status = String::from("success");
-                self.presentations.insert(&playback_id, &presentation);
-                
-                true
-            }
-            None => {
-                false
-            }
+/// Takes the next id from `counter`. Clients that still pick their own id pass `explicit_id`,
+/// any free id is accepted. Explicit ids above the counter are kept in `taken`, so generated
+/// ids skip them without scanning.
+pub(crate) fn next_id(counter: &mut u64, taken: &mut TakenIds, explicit_id: Option<u64>) -> u64 {
+    match explicit_id {
+        Some(id) if id == *counter => {
+            skip_taken_ids(counter, taken);
+            id
+        }
+        Some(id) if id > *counter => {
+            take_id(taken, id);
+            id
+        }
+        Some(id) => id,
+        None => {
+            let id = *counter;
+            skip_taken_ids(counter, taken);
+            id
+        }
+    }
+}
+
+/// Moves `counter` past its current id and past the range of taken ids that follows it
+fn skip_taken_ids(counter: &mut u64, taken: &mut TakenIds) {
+    let next = counter.checked_add(1).expect("Abort. Ids exhausted");
+    *counter = match taken.remove(&next) {
+        Some(last) => last.checked_add(1).expect("Abort. Ids exhausted"),
+        None => next,
+    };
+}
+
+/// Adds `id` to the taken ranges, merging it with the ranges right before and after it.
+/// Taking an id twice changes nothing, the caller rejects the duplicate record.
+fn take_id(taken: &mut TakenIds, id: u64) {
+    if taken.get(&id).is_some() {
+        return;
+    }
+    let mut first = id;
+    if let Some(start) = taken.lower(&id) {
+        let last = taken.get(&start).unwrap();
+        if last >= id {
+            return;
+        }
+        if last + 1 == id {
+            first = start;
         }
     }
+    let last = match id.checked_add(1).and_then(|next| taken.remove(&next)) {
+        Some(last) => last,
+        None => id,
+    };
+    taken.insert(&first, &last);
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
 
+    fn setup() -> (u64, TakenIds) {
+        set_caller("metaads.near", 0, 0);
+        (5, TreeMap::new(b"t".to_vec()))
+    }
+
+    #[test]
+    fn explicit_ids_below_the_counter_do_not_move_it() {
+        let (mut counter, mut taken) = setup();
+        assert_eq!(next_id(&mut counter, &mut taken, Some(3)), 3);
+        assert_eq!(next_id(&mut counter, &mut taken, None), 5);
+        assert_eq!(next_id(&mut counter, &mut taken, None), 6);
+        assert_eq!(counter, 7);
+    }
+
+    #[test]
+    fn generated_ids_skip_explicit_ids_above_the_counter() {
+        let (mut counter, mut taken) = setup();
+        for id in [9, 7, 6, 8, 12].iter() {
+            assert_eq!(next_id(&mut counter, &mut taken, Some(*id)), *id);
+        }
+        assert_eq!(taken.to_vec(), vec![(6, 9), (12, 12)]);
+
+        assert_eq!(next_id(&mut counter, &mut taken, None), 5);
+        assert_eq!(next_id(&mut counter, &mut taken, None), 10);
+        assert_eq!(next_id(&mut counter, &mut taken, Some(11)), 11);
+        assert_eq!(counter, 13);
+        assert_eq!(taken.len(), 0);
+    }
+
+    #[test]
+    fn explicit_ids_at_the_end_of_the_range() {
+        let (mut counter, mut taken) = setup();
+        next_id(&mut counter, &mut taken, Some(u64::MAX));
+        next_id(&mut counter, &mut taken, Some(u64::MAX - 1));
+        next_id(&mut counter, &mut taken, Some(u64::MAX));
+        assert_eq!(taken.to_vec(), vec![(u64::MAX - 1, u64::MAX)]);
+        assert_eq!(next_id(&mut counter, &mut taken, None), 5);
+    }
+}
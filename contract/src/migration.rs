@@ -0,0 +1,491 @@
+use crate::*;
+
+/// `AdSpot` as stored by state version 1
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldAdSpot {
+    pub adspot_id: u64,
+    pub owner_account_id: AccountId,
+    pub price: Balance,
+    pub name: String,
+    pub publisher_earn: Option<u64>,
+    pub show_kind: Option<String>,
+}
+
+/// `Creative` as stored by state version 1
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldCreative {
+    pub creative_id: u64,
+    pub name: String,
+    pub content: String,
+    pub nft_cid: Option<String>,
+    pub owner_account_id: AccountId,
+}
+
+/// `Presentation` as stored by state version 1
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldPresentation {
+    pub playback_id: u64,
+    pub adspot_id: u64,
+    pub creative_id: u64,
+    pub advertiser_cost: Balance,
+    pub start_time: Timestamp,
+    pub end_time: Timestamp,
+    pub transfered: bool,
+    pub advertiser_account_id: AccountId,
+    pub publisher_account_id: AccountId,
+    pub ad_spot_name: String,
+    pub publisher_earn: Option<u64>,
+    pub show_kind: Option<String>,
+    pub entertainment: String,
+    pub entertainment_fee: Balance,
+    pub status: String,
+}
+
+/// The contract state of version 1, initialized through `Default`
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct OldMetaAdsContract {
+    pub creatives: UnorderedMap<u64, OldCreative>,
+    pub presentations: UnorderedMap<u64, OldPresentation>,
+    pub ad_spots: UnorderedMap<u64, OldAdSpot>,
+}
+
+impl From<OldAdSpot> for AdSpot {
+    fn from(old: OldAdSpot) -> Self {
+        Self {
+            adspot_id: old.adspot_id,
+            owner_account_id: old.owner_account_id,
+            price: old.price,
+            pricing: PricingModel::Flat,
+            accepted_tokens: HashMap::new(),
+            name: old.name,
+            publisher_earn: old.publisher_earn,
+            show_kind: old.show_kind,
+            cancellation_penalty: None,
+            listed: true,
+            approved_creatives_only: false,
+            accepted_formats: vec![],
+        }
+    }
+}
+
+impl From<OldCreative> for Creative {
+    fn from(old: OldCreative) -> Self {
+        Self {
+            creative_id: old.creative_id,
+            name: old.name,
+            content_hash: content_hash_or_sha256(&old.content, None),
+            version: 1,
+            format: None,
+            content: old.content,
+            moderation: ModerationStatus::Pending,
+            archived: false,
+            nft_cid: old.nft_cid,
+            nft: None,
+            owner_account_id: old.owner_account_id,
+        }
+    }
+}
+
+impl From<OldPresentation> for Presentation {
+    fn from(old: OldPresentation) -> Self {
+        // The first version only knew "signed" and "success", with `transfered` set on payout
+        let status = if old.transfered || old.status == "success" {
+            PresentationStatus::Completed
+        } else {
+            PresentationStatus::Signed
+        };
+
+        Self {
+            playback_id: old.playback_id,
+            adspot_id: old.adspot_id,
+            creative_id: old.creative_id,
+            // Creatives had no versions, the hash is filled in from the creative by the migration
+            creative_version: 1,
+            creative_content_hash: Base64VecU8(vec![]),
+            advertiser_cost: old.advertiser_cost,
+            token_id: None,
+            pricing: PricingModel::Flat,
+            impressions: None,
+            start_time: old.start_time,
+            end_time: old.end_time,
+            advertiser_account_id: old.advertiser_account_id,
+            publisher_account_id: old.publisher_account_id,
+            ad_spot_name: old.ad_spot_name,
+            publisher_earn: old.publisher_earn,
+            show_kind: old.show_kind,
+            entertainment: old.entertainment,
+            entertainment_fee: old.entertainment_fee,
+            storage_cost: 0,
+            fee_bps: DEFAULT_PLATFORM_FEE_BPS,
+            status,
+            cancellation_penalty: None,
+        }
+    }
+}
+
+/// Key of the `Migration` in progress
+const MIGRATION_KEY: &[u8] = b"MIGRATION";
+/// Key under which near-sdk stores the contract struct
+const STATE_KEY: &[u8] = b"STATE";
+
+pub(crate) fn migration_in_progress() -> bool {
+    env::storage_has_key(MIGRATION_KEY)
+}
+
+/// A migration that rewrites the records over several calls, so that large states stay within
+/// the gas limit of a call. The contract state is removed until the migration finishes, every
+/// method then fails as on a contract that is not initialized and never sees records of both layouts.
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct Migration {
+    /// The migrated contract. Its maps still hold old records from the positions below on.
+    contract: MetaAdsContract,
+    ad_spots: u64,
+    creatives: u64,
+    presentations: u64,
+}
+
+/// The id and the record at `index` of `map`, the record still in the old layout
+fn old_record<Old, New>(map: &UnorderedMap<u64, New>, index: u64) -> (u64, Old)
+where
+    Old: BorshDeserialize,
+    New: BorshSerialize + BorshDeserialize,
+{
+    let id = map.keys_as_vector().get(index).expect("Abort. Record not found");
+    let raw = map.values_as_vector().get_raw(index).expect("Abort. Record not found");
+    (id, Old::try_from_slice(&raw).expect("Abort. Corrupted record"))
+}
+
+/// Replaces the record of `id` in place. A typed insert would deserialize the replaced
+/// value, which has the old layout.
+fn replace_record<New>(map: &mut UnorderedMap<u64, New>, id: u64, record: &New)
+where
+    New: BorshSerialize + BorshDeserialize,
+{
+    map.insert_raw(&id.try_to_vec().unwrap(), &record.try_to_vec().unwrap());
+}
+
+/// The same map with a new value type. `UnorderedMap` stores only prefixes and lengths
+/// in the contract state, so the same state bytes describe the map with the new value type.
+fn retype_map<Old, New>(old: &UnorderedMap<u64, Old>) -> UnorderedMap<u64, New>
+where
+    Old: BorshSerialize + BorshDeserialize,
+    New: BorshSerialize + BorshDeserialize,
+{
+    UnorderedMap::try_from_slice(&old.try_to_vec().unwrap()).unwrap()
+}
+
+#[near_bindgen]
+impl MetaAdsContract {
+    /// Starts rewriting the stored state into the current layout and migrates up to `limit`
+    /// records. Deploy the new code and call it in the same transaction. Returns whether the
+    /// migration finished, otherwise call `continue_migration` until it does.
+    #[private]
+    pub fn migrate(limit: u64) -> bool {
+        assert!(!migration_in_progress(), "Abort. A migration is in progress, call continue_migration");
+        let version = read_state_version();
+        assert!(version < STATE_VERSION, "Abort. The state is already at version {}", version);
+
+        let migration = match version {
+            1 => Migration::from_v1(),
+            _ => env::panic(format!("Abort. Unknown state version {}", version).as_bytes()),
+        };
+        env::storage_remove(STATE_KEY);
+        migration.run(limit)
+    }
+
+    /// Migrates up to `limit` more records. Returns whether the migration finished.
+    #[private]
+    pub fn continue_migration(limit: u64) -> bool {
+        let migration: Migration = env::storage_read(MIGRATION_KEY)
+            .map(|bytes| Migration::try_from_slice(&bytes).expect("Abort. Corrupted migration"))
+            .unwrap_or_else(|| env::panic(b"Abort. No migration in progress"));
+        migration.run(limit)
+    }
+}
+
+impl Migration {
+    fn from_v1() -> Self {
+        let old: OldMetaAdsContract = env::state_read().expect("Abort. Contract state not found");
+
+        // Until now only the contract account itself could call the privileged methods
+        let contract = MetaAdsContract {
+            creatives: retype_map(&old.creatives),
+            presentations: retype_map(&old.presentations),
+            ad_spots: retype_map(&old.ad_spots),
+            ..MetaAdsContract::from_config(Config { owner_id: env::current_account_id(), platform_fee_bps: None })
+        };
+        Self { contract, ad_spots: 0, creatives: 0, presentations: 0 }
+    }
+
+    /// Migrates up to `limit` records, ad spots first, then creatives, then the presentations
+    /// that refer to both. Stores the contract once every record is migrated.
+    fn run(mut self, limit: u64) -> bool {
+        for _ in 0..limit {
+            if self.ad_spots < self.contract.ad_spots.len() {
+                self.migrate_ad_spot();
+            } else if self.creatives < self.contract.creatives.len() {
+                self.migrate_creative();
+            } else if self.presentations < self.contract.presentations.len() {
+                self.migrate_presentation();
+            } else {
+                break;
+            }
+        }
+
+        let finished = self.presentations == self.contract.presentations.len()
+            && self.creatives == self.contract.creatives.len()
+            && self.ad_spots == self.contract.ad_spots.len();
+        if finished {
+            env::storage_remove(MIGRATION_KEY);
+            env::state_write(&self.contract);
+            write_state_version(STATE_VERSION);
+            log!("Migration to state version {} finished", STATE_VERSION);
+        } else {
+            env::storage_write(MIGRATION_KEY, &self.try_to_vec().unwrap());
+            log!(
+                "Migrated {} Ad Spots, {} creatives and {} presentations",
+                self.ad_spots, self.creatives, self.presentations
+            );
+        }
+        finished
+    }
+
+    fn migrate_ad_spot(&mut self) {
+        let contract = &mut self.contract;
+        let (id, old): (u64, OldAdSpot) = old_record(&contract.ad_spots, self.ad_spots);
+        let ad_spot = AdSpot::from(old);
+        replace_record(&mut contract.ad_spots, id, &ad_spot);
+
+        contract.next_adspot_id = contract.next_adspot_id.max(id + 1);
+        contract.internal_index_adspot(&ad_spot);
+        self.ad_spots += 1;
+    }
+
+    fn migrate_creative(&mut self) {
+        let contract = &mut self.contract;
+        let (id, old): (u64, OldCreative) = old_record(&contract.creatives, self.creatives);
+        let creative = Creative::from(old);
+        replace_record(&mut contract.creatives, id, &creative);
+
+        contract.next_creative_id = contract.next_creative_id.max(id + 1);
+        contract.internal_index_creative(&creative);
+        contract.internal_add_creative_version(&creative);
+        contract.pending_creatives.insert(&creative.creative_id);
+        self.creatives += 1;
+    }
+
+    /// Runs after every creative is migrated, so the content hash can be taken from the creative
+    fn migrate_presentation(&mut self) {
+        let contract = &mut self.contract;
+        let (id, old): (u64, OldPresentation) = old_record(&contract.presentations, self.presentations);
+        if old.transfered {
+            contract.treasury.fees_earned += old.entertainment_fee;
+        } else {
+            contract.treasury.escrowed += old.advertiser_cost;
+        }
+
+        let mut presentation = Presentation::from(old);
+        if let Some(creative) = contract.creatives.get(&presentation.creative_id) {
+            presentation.creative_content_hash = creative.content_hash;
+        }
+        replace_record(&mut contract.presentations, id, &presentation);
+
+        contract.next_playback_id = contract.next_playback_id.max(id + 1);
+        contract.internal_add_booking(&presentation);
+        contract.internal_index_presentation(&presentation);
+        contract.internal_update_open_presentations(&presentation);
+        self.presentations += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn setup() {
+        set_caller("metaads.near", 0, 0);
+    }
+
+    fn old_presentation(playback_id: u64, transfered: bool) -> OldPresentation {
+        OldPresentation {
+            playback_id,
+            adspot_id: 1,
+            creative_id: 1,
+            advertiser_cost: NEAR,
+            start_time: 100,
+            end_time: 200,
+            transfered,
+            advertiser_account_id: "advertiser.near".to_string(),
+            publisher_account_id: "publisher.near".to_string(),
+            ad_spot_name: "Banner".to_string(),
+            publisher_earn: None,
+            show_kind: None,
+            entertainment: "metaads.near".to_string(),
+            entertainment_fee: NEAR / 10,
+            status: String::from(if transfered { "success" } else { "signed" }),
+        }
+    }
+
+    fn write_v1_state() {
+        let mut old = OldMetaAdsContract {
+            creatives: UnorderedMap::new(StorageKey::Creatives.try_to_vec().unwrap()),
+            presentations: UnorderedMap::new(StorageKey::Presentations.try_to_vec().unwrap()),
+            ad_spots: UnorderedMap::new(StorageKey::AdSpot.try_to_vec().unwrap()),
+        };
+        old.ad_spots.insert(
+            &1,
+            &OldAdSpot {
+                adspot_id: 1,
+                owner_account_id: "publisher.near".to_string(),
+                price: NEAR,
+                name: "Banner".to_string(),
+                publisher_earn: Some(5),
+                show_kind: Some("banner".to_string()),
+            },
+        );
+        old.creatives.insert(
+            &1,
+            &OldCreative {
+                creative_id: 1,
+                name: "Spring campaign".to_string(),
+                content: "ipfs://creative".to_string(),
+                nft_cid: Some("bafy".to_string()),
+                owner_account_id: "advertiser.near".to_string(),
+            },
+        );
+        old.presentations.insert(&1, &old_presentation(1, false));
+        old.presentations.insert(&2, &old_presentation(2, true));
+        env::state_write(&old);
+    }
+
+    #[test]
+    fn migrate_from_v1() {
+        setup();
+        write_v1_state();
+
+        assert!(MetaAdsContract::migrate(100));
+        let contract: MetaAdsContract = env::state_read().unwrap();
+
+        assert_eq!(read_state_version(), STATE_VERSION);
+        assert_eq!(contract.owner_id, "metaads.near");
+        assert_eq!(contract.platform_fee_bps, DEFAULT_PLATFORM_FEE_BPS);
+
+        let adspot = contract.fetch_adspot_by_id(1);
+        assert_eq!(adspot.owner_account_id, "publisher.near");
+        assert_eq!(adspot.publisher_earn, Some(5));
+        assert_eq!(adspot.cancellation_penalty, None);
+        assert!(adspot.listed);
+        assert!(adspot.accepted_formats.is_empty());
+
+        let creative = contract.fetch_creative_by_id(1);
+        assert_eq!(creative.nft_cid, Some("bafy".to_string()));
+        assert_eq!(creative.nft, None);
+        assert_eq!(creative.format, None);
+        assert_eq!(creative.version, 1);
+        assert_eq!(creative.content_hash.0, env::sha256(b"ipfs://creative"));
+        assert_eq!(contract.fetch_creative_versions(1, None, None).len(), 1);
+        assert_eq!(creative.moderation, ModerationStatus::Pending);
+        assert_eq!(contract.fetch_moderation_queue(None, None).len(), 1);
+        assert_eq!(contract.fetch_creatives_count_for_owner("advertiser.near".to_string()), U128(1));
+
+        assert_eq!(contract.presentations.len(), 2);
+        let signed = contract.fetch_presentation_by_id(1);
+        assert_eq!(signed.status, PresentationStatus::Signed);
+        assert_eq!(signed.fee_bps, DEFAULT_PLATFORM_FEE_BPS);
+        assert_eq!(signed.creative_version, 1);
+        assert_eq!(signed.creative_content_hash, creative.content_hash);
+        assert_eq!(contract.fetch_presentation_by_id(2).status, PresentationStatus::Completed);
+
+        assert_eq!(contract.open_presentations.to_vec(), vec![((200, 1), ())]);
+
+        assert_eq!(contract.next_adspot_id, 2);
+        assert_eq!(contract.next_creative_id, 2);
+        assert_eq!(contract.next_playback_id, 3);
+
+        assert_eq!(contract.treasury.escrowed, NEAR);
+        assert_eq!(contract.treasury.fees_earned, NEAR / 10);
+
+        assert_eq!(contract.fetch_adspots_for_owner("publisher.near".to_string(), None, None).len(), 1);
+        assert_eq!(contract.fetch_presentations_count_for_advertiser("advertiser.near".to_string()), U128(2));
+        let for_publisher = contract.fetch_presentations_for_publisher("publisher.near".to_string(), Some(U128(1)), Some(10));
+        assert_eq!(for_publisher.len(), 1);
+        assert_eq!(for_publisher[0].playback_id, 2);
+        assert_eq!(contract.fetch_presentations_count_for_publisher("advertiser.near".to_string()), U128(0));
+        assert_eq!(contract.fetch_presentations_count_for_creative(1), U128(2));
+        let completed = contract.fetch_presentations_for_adspot(1, Some(PresentationStatus::Completed), None, None, None);
+        assert_eq!(completed.len(), 1);
+        assert_eq!(completed[0].playback_id, 2);
+        assert_eq!(contract.fetch_presentations_for_adspot(1, None, Some(Timeframe::Upcoming), None, None).len(), 2);
+        assert!(contract.fetch_presentations_for_adspot(1, None, Some(Timeframe::Past), None, None).is_empty());
+
+        let schedule = contract.fetch_adspot_schedule(1, 0, 300);
+        assert_eq!(schedule.len(), 4);
+        assert_eq!(schedule[0], ScheduleWindow { start_time: 0, end_time: 100, playback_id: None });
+        assert_eq!(schedule[1], ScheduleWindow { start_time: 100, end_time: 200, playback_id: Some(1) });
+        assert_eq!(schedule[3], ScheduleWindow { start_time: 200, end_time: 300, playback_id: None });
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. The state is already at version")]
+    fn migrate_twice() {
+        setup();
+        write_v1_state();
+
+        assert!(MetaAdsContract::migrate(100));
+        MetaAdsContract::migrate(100);
+    }
+
+    #[test]
+    fn migrate_in_batches() {
+        setup();
+        write_v1_state();
+
+        assert!(!MetaAdsContract::migrate(1));
+        assert!(!env::state_exists());
+        assert_eq!(read_state_version(), 1);
+        assert!(!MetaAdsContract::continue_migration(2));
+        assert!(MetaAdsContract::continue_migration(1));
+        assert!(!migration_in_progress());
+
+        let contract: MetaAdsContract = env::state_read().unwrap();
+        assert_eq!(read_state_version(), STATE_VERSION);
+        assert_eq!(contract.fetch_creative_by_id(1).version, 1);
+        assert_eq!(contract.fetch_presentation_by_id(1).creative_content_hash.0, env::sha256(b"ipfs://creative"));
+        assert_eq!(contract.fetch_presentation_by_id(2).status, PresentationStatus::Completed);
+        assert_eq!(contract.treasury.escrowed, NEAR);
+        assert_eq!(contract.treasury.fees_earned, NEAR / 10);
+        assert_eq!(contract.next_playback_id, 3);
+        assert_eq!(contract.open_presentations.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. A migration is in progress")]
+    fn new_during_a_migration() {
+        setup();
+        write_v1_state();
+        MetaAdsContract::migrate(1);
+
+        MetaAdsContract::new(Config { owner_id: "owner.near".to_string(), platform_fee_bps: None });
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. No migration in progress")]
+    fn continue_without_a_migration() {
+        setup();
+        MetaAdsContract::continue_migration(10);
+    }
+
+    #[test]
+    fn new_writes_current_version() {
+        setup();
+
+        let contract = MetaAdsContract::new(Config {
+            owner_id: "owner.near".to_string(),
+            platform_fee_bps: Some(250),
+        });
+
+        assert_eq!(contract.fetch_state_version(), STATE_VERSION);
+        assert_eq!(contract.fetch_platform_fee(), 250);
+    }
+}
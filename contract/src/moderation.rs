@@ -0,0 +1,141 @@
+use crate::*;
+
+/// Longest reason a moderator can give for a rejection
+pub const MAX_REJECTION_REASON_LEN: usize = 500;
+
+/// Moderation of the current version of a creative
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum ModerationStatus {
+    Pending,
+    Approved,
+    Rejected { reason: String },
+}
+
+#[near_bindgen]
+impl MetaAdsContract {
+
+    pub fn approve_creative(&mut self, creative_id: u64) -> Creative {
+        self.assert_role(Role::Moderator);
+        let mut creative = self.fetch_creative_by_id(creative_id);
+
+        self.internal_set_moderation(&mut creative, ModerationStatus::Approved);
+        EventLog::new(EventLogVariant::CreativeApproved(vec![CreativeModerationLog {
+            creative_id,
+            version: creative.version,
+            moderator_id: env::predecessor_account_id(),
+            reason: None,
+        }]))
+        .emit();
+
+        creative
+    }
+
+    /// Rejected creatives cannot be booked until a new version is approved
+    pub fn reject_creative(&mut self, creative_id: u64, reason: String) -> Creative {
+        self.assert_role(Role::Moderator);
+        assert!(!reason.is_empty(), "Abort. Reason is empty");
+        assert!(
+            reason.len() <= MAX_REJECTION_REASON_LEN,
+            "Abort. Reason is longer than {} characters",
+            MAX_REJECTION_REASON_LEN
+        );
+        let mut creative = self.fetch_creative_by_id(creative_id);
+
+        self.internal_set_moderation(&mut creative, ModerationStatus::Rejected { reason: reason.clone() });
+        EventLog::new(EventLogVariant::CreativeRejected(vec![CreativeModerationLog {
+            creative_id,
+            version: creative.version,
+            moderator_id: env::predecessor_account_id(),
+            reason: Some(reason),
+        }]))
+        .emit();
+
+        creative
+    }
+
+    pub fn fetch_moderation_queue_count(&self) -> U128 {
+        U128(self.pending_creatives.len() as u128)
+    }
+
+    /// Creatives waiting for a moderator
+    pub fn fetch_moderation_queue(&self, from_index: Option<U128>, limit: Option<u64>) -> Vec<Creative> {
+        let ids = self.pending_creatives.as_vector();
+        page_range(ids.len(), from_index, limit)
+            .map(|index| self.fetch_creative_by_id(ids.get(index).unwrap()))
+            .collect()
+    }
+}
+
+impl MetaAdsContract {
+    /// Saves the creative with `status` and keeps the moderation queue in sync.
+    /// The storage the queue frees goes back to the creative owner.
+    pub(crate) fn internal_set_moderation(&mut self, creative: &mut Creative, status: ModerationStatus) {
+        let storage_before = env::storage_usage();
+
+        if status == ModerationStatus::Pending {
+            self.pending_creatives.insert(&creative.creative_id);
+        } else {
+            self.pending_creatives.remove(&creative.creative_id);
+        }
+        creative.moderation = status;
+        self.creatives.insert(&creative.creative_id, creative);
+
+        if env::storage_usage() < storage_before {
+            self.internal_update_storage(&creative.owner_account_id, storage_before, 0);
+        }
+    }
+}
+
+/// Rejected creatives are never booked, Ad Spots can also require an approval
+pub(crate) fn assert_creative_allowed_on(creative: &Creative, adspot: &AdSpot) {
+    if let ModerationStatus::Rejected { reason } = &creative.moderation {
+        env::panic(format!("Abort. Creative was rejected: {}", reason).as_bytes());
+    }
+    if adspot.approved_creatives_only {
+        assert!(
+            creative.moderation == ModerationStatus::Approved,
+            "Abort. Ad Spot accepts only approved creatives"
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    /// The contract owner passes the moderator check and owns the creative
+    fn setup_with_creative() -> (MetaAdsContract, u64) {
+        set_caller("owner.near", NEAR, 0);
+        let mut contract = new_contract();
+        let creative = make_creative(&mut contract, "ipfs://v1");
+        (contract, creative.creative_id)
+    }
+
+    #[test]
+    fn new_versions_go_back_to_the_queue() {
+        let (mut contract, creative_id) = setup_with_creative();
+        assert_eq!(contract.fetch_moderation_queue_count(), U128(1));
+
+        let rejected = contract.reject_creative(creative_id, "Misleading claims".to_string());
+        assert_eq!(rejected.moderation, ModerationStatus::Rejected { reason: "Misleading claims".to_string() });
+        assert_eq!(contract.fetch_moderation_queue_count(), U128(0));
+
+        contract.update_creative(creative_id, None, Some("ipfs://v2".to_string()), None, None);
+        assert_eq!(contract.fetch_creative_by_id(creative_id).moderation, ModerationStatus::Pending);
+        assert_eq!(contract.fetch_moderation_queue(None, None)[0].version, 2);
+
+        assert_eq!(contract.approve_creative(creative_id).moderation, ModerationStatus::Approved);
+        assert!(contract.fetch_moderation_queue(None, None).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Requires the Moderator role")]
+    fn only_moderators_decide() {
+        let (mut contract, creative_id) = setup_with_creative();
+        set_caller("advertiser.near", 0, 0);
+        contract.approve_creative(creative_id);
+    }
+}
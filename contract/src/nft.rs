@@ -0,0 +1,271 @@
+use crate::*;
+
+const GAS_FOR_NFT_TOKEN: Gas = 10_000_000_000_000;
+const GAS_FOR_ON_CREATIVE_NFT_VERIFIED: Gas = 50_000_000_000_000;
+const GAS_FOR_ADD_VERIFIED_CREATIVE: Gas = 25_000_000_000_000;
+const GAS_FOR_ON_BOOKING_NFT_VERIFIED: Gas = 70_000_000_000_000;
+const GAS_FOR_BOOK_VERIFIED: Gas = 40_000_000_000_000;
+const GAS_FOR_ON_TOKEN_BOOKING_NFT_VERIFIED: Gas = 50_000_000_000_000;
+
+pub type TokenId = String;
+
+/// A token of a NEP-171 contract, like the `nft-contract` of this repository
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftReference {
+    pub contract_id: AccountId,
+    pub token_id: TokenId,
+}
+
+/// The part of the `nft_token` result the contract reads
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct NftToken {
+    pub owner_id: AccountId,
+}
+
+#[ext_contract(ext_nft)]
+pub trait NonFungibleToken {
+    fn nft_token(&self, token_id: TokenId) -> Option<NftToken>;
+}
+
+/// Callbacks of the ownership checks of creatives that reference an NFT. The creative is
+/// created, or the presentation booked, only if the NFT is held by the caller.
+///
+/// The NEAR attached to `make_creative` or `do_agreement` is already in the contract when
+/// the check resolves, so a panic would keep it. The creative is therefore created, and the
+/// presentation booked, in a call of the contract to itself. A failed call reverts only its
+/// own changes and the resolver returns the deposit.
+#[near_bindgen]
+impl MetaAdsContract {
+
+    #[private]
+    pub fn on_creative_nft_verified(&mut self, creative: Creative, attached: U128) -> PromiseOrValue<Option<Creative>> {
+        let owner_account_id = creative.owner_account_id.clone();
+
+        if !nft_held_by(&owner_account_id) {
+            log!("Abort. The NFT of the creative is not held by {}", owner_account_id);
+            refund_deposit_to(&owner_account_id, attached.0);
+            return PromiseOrValue::Value(None);
+        }
+
+        PromiseOrValue::Promise(
+            ext_self::add_verified_creative(creative, attached, &env::current_account_id(), 0, GAS_FOR_ADD_VERIFIED_CREATIVE)
+                .then(ext_self::on_verified_creative_resolved(
+                    owner_account_id,
+                    attached,
+                    &env::current_account_id(),
+                    0,
+                    GAS_FOR_RESOLVE_TRANSFER,
+                )),
+        )
+    }
+
+    #[private]
+    pub fn add_verified_creative(&mut self, creative: Creative, attached: U128) -> Creative {
+        let unused = self.internal_add_creative(&creative, attached.0);
+        refund_deposit_to(&creative.owner_account_id, unused);
+
+        creative
+    }
+
+    #[private]
+    pub fn on_verified_creative_resolved(&mut self, owner_account_id: AccountId, attached: U128) -> Option<Creative> {
+        resolved_or_refunded(&owner_account_id, attached.0)
+    }
+
+    #[private]
+    pub fn on_booking_nft_verified(
+        &mut self,
+        advertiser_account_id: AccountId,
+        booking: BookingMessage,
+        amount: U128,
+    ) -> PromiseOrValue<Option<Presentation>> {
+        if !nft_held_by(&advertiser_account_id) {
+            log!("Abort. The NFT of creative {} is not held by {}", booking.creative_id, advertiser_account_id);
+            refund_deposit_to(&advertiser_account_id, amount.0);
+            return PromiseOrValue::Value(None);
+        }
+
+        PromiseOrValue::Promise(
+            ext_self::book_verified(
+                advertiser_account_id.clone(),
+                booking,
+                amount,
+                &env::current_account_id(),
+                0,
+                GAS_FOR_BOOK_VERIFIED,
+            )
+            .then(ext_self::on_verified_booking_resolved(
+                advertiser_account_id,
+                amount,
+                &env::current_account_id(),
+                0,
+                GAS_FOR_RESOLVE_TRANSFER,
+            )),
+        )
+    }
+
+    #[private]
+    pub fn book_verified(&mut self, advertiser_account_id: AccountId, booking: BookingMessage, amount: U128) -> Presentation {
+        let (presentation, unused) = self.internal_do_agreement(advertiser_account_id.clone(), booking, None, amount.0);
+        refund_deposit_to(&advertiser_account_id, unused);
+
+        presentation
+    }
+
+    #[private]
+    pub fn on_verified_booking_resolved(&mut self, advertiser_account_id: AccountId, amount: U128) -> Option<Presentation> {
+        resolved_or_refunded(&advertiser_account_id, amount.0)
+    }
+
+    /// Returns the unused tokens to `ft_transfer_call`. A panic here is safe, the token
+    /// contract then returns all the tokens in `ft_resolve_transfer`.
+    #[private]
+    pub fn on_token_booking_nft_verified(
+        &mut self,
+        advertiser_account_id: AccountId,
+        booking: BookingMessage,
+        token_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        if !nft_held_by(&advertiser_account_id) {
+            log!("Abort. The NFT of creative {} is not held by {}", booking.creative_id, advertiser_account_id);
+            return amount;
+        }
+
+        let (_, unused) = self.internal_do_agreement(advertiser_account_id, booking, Some(token_id), amount.0);
+        U128(unused)
+    }
+}
+
+impl MetaAdsContract {
+    /// The NFT the creative references, if any
+    pub(crate) fn internal_creative_nft(&self, creative_id: u64) -> Option<NftReference> {
+        self.creatives.get(&creative_id).and_then(|creative| creative.nft)
+    }
+
+    /// Checks the NFT of the creative, then books with `internal_do_agreement`. The booking
+    /// is validated before the check to fail early, it is validated again once the check resolves.
+    pub(crate) fn internal_book_with_nft(
+        &mut self,
+        nft: &NftReference,
+        advertiser_account_id: AccountId,
+        booking: BookingMessage,
+        token_id: Option<AccountId>,
+        amount: Balance,
+    ) -> Promise {
+        self.internal_validate_booking(&advertiser_account_id, &booking, &token_id, amount);
+
+        let callback = match token_id {
+            Some(token_id) => ext_self::on_token_booking_nft_verified(
+                advertiser_account_id,
+                booking,
+                token_id,
+                U128(amount),
+                &env::current_account_id(),
+                0,
+                GAS_FOR_ON_TOKEN_BOOKING_NFT_VERIFIED,
+            ),
+            None => ext_self::on_booking_nft_verified(
+                advertiser_account_id,
+                booking,
+                U128(amount),
+                &env::current_account_id(),
+                0,
+                GAS_FOR_ON_BOOKING_NFT_VERIFIED,
+            ),
+        };
+        fetch_nft_token(nft).then(callback)
+    }
+
+    pub(crate) fn internal_add_creative_with_nft(&mut self, nft: &NftReference, creative: Creative, attached: Balance) -> Promise {
+        fetch_nft_token(nft).then(ext_self::on_creative_nft_verified(
+            creative,
+            U128(attached),
+            &env::current_account_id(),
+            0,
+            GAS_FOR_ON_CREATIVE_NFT_VERIFIED,
+        ))
+    }
+}
+
+pub(crate) fn assert_valid_nft_reference(nft: &NftReference) {
+    assert!(env::is_valid_account_id(nft.contract_id.as_bytes()), "Abort. Invalid NFT contract id");
+    assert!(!nft.token_id.is_empty(), "Abort. NFT token id is empty");
+}
+
+fn fetch_nft_token(nft: &NftReference) -> Promise {
+    ext_nft::nft_token(nft.token_id.clone(), &nft.contract_id, 0, GAS_FOR_NFT_TOKEN)
+}
+
+/// Whether the `nft_token` call the callback resolves returned a token held by `account_id`
+fn nft_held_by(account_id: &AccountId) -> bool {
+    assert_eq!(env::promise_results_count(), 1, "Abort. Expected one promise result");
+
+    match env::promise_result(0) {
+        PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<Option<NftToken>>(&value)
+            .ok()
+            .flatten()
+            .is_some_and(|token| &token.owner_id == account_id),
+        _ => false,
+    }
+}
+
+/// The result of the call the resolver follows. When the call failed nothing was stored,
+/// so the whole deposit goes back to `account_id`.
+fn resolved_or_refunded<T: near_sdk::serde::de::DeserializeOwned>(account_id: &AccountId, deposit: Balance) -> Option<T> {
+    assert_eq!(env::promise_results_count(), 1, "Abort. Expected one promise result");
+
+    match env::promise_result(0) {
+        PromiseResult::Successful(value) => near_sdk::serde_json::from_slice(&value).ok(),
+        _ => {
+            log!("The call failed. Refunded {} to {}", deposit, account_id);
+            refund_deposit_to(account_id, deposit);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::test_utils::get_created_receipts;
+
+    #[test]
+    fn nft_held_by_owner_only() {
+        let token = br#"{"token_id":"1","owner_id":"advertiser.near","metadata":{},"approved_account_ids":{}}"#;
+
+        resolve_as_contract(PromiseResult::Successful(token.to_vec()));
+        assert!(nft_held_by(&"advertiser.near".to_string()));
+        assert!(!nft_held_by(&"someone.near".to_string()));
+    }
+
+    #[test]
+    fn nft_not_held_when_missing_or_failed() {
+        resolve_as_contract(PromiseResult::Successful(b"null".to_vec()));
+        assert!(!nft_held_by(&"advertiser.near".to_string()));
+
+        resolve_as_contract(PromiseResult::Failed);
+        assert!(!nft_held_by(&"advertiser.near".to_string()));
+    }
+
+    #[test]
+    fn failed_verified_call_refunds_the_deposit() {
+        resolve_as_contract(PromiseResult::Failed);
+        let resolved: Option<Presentation> = resolved_or_refunded(&"advertiser.near".to_string(), 5);
+
+        assert!(resolved.is_none());
+        assert_eq!(get_created_receipts().len(), 1);
+    }
+
+    #[test]
+    fn succeeded_verified_call_returns_its_result() {
+        resolve_as_contract(PromiseResult::Successful(b"7".to_vec()));
+        let resolved: Option<u64> = resolved_or_refunded(&"advertiser.near".to_string(), 5);
+
+        assert_eq!(resolved, Some(7));
+        assert!(get_created_receipts().is_empty());
+    }
+}
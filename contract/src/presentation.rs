@@ -1,15 +1,116 @@
     use crate::*;
 
-#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize)]
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum PresentationStatus {
+    /// Paid by the advertiser, waiting for the publisher to accept it
+    Pending,
+    /// Accepted by the publisher, the deposit is held until the show is over
+    Signed,
+    /// Reserved, no method moves a presentation here yet
+    Running,
+    /// The payout to the publisher is in flight
+    Settling,
+    /// The publisher was paid
+    Completed,
+    /// Cancelled by the advertiser before the start time
+    Cancelled,
+    /// Rejected or never answered by the publisher, the advertiser got the deposit back
+    Refunded,
+    /// Reserved, no method moves a presentation here yet. A disputed presentation can only
+    /// be refunded, never settled.
+    Disputed,
+}
+
+impl PresentationStatus {
+    pub fn can_transition_to(&self, next: PresentationStatus) -> bool {
+        use PresentationStatus::*;
+
+        matches!(
+            (self, next),
+            (Pending, Signed)
+                | (Pending, Cancelled)
+                | (Pending, Refunded)
+                | (Signed, Running)
+                | (Signed, Cancelled)
+                | (Signed, Settling)
+                | (Signed, Disputed)
+                | (Running, Settling)
+                | (Running, Disputed)
+                | (Settling, Completed)
+                | (Disputed, Refunded)
+        )
+    }
+
+    pub fn assert_can_transition_to(&self, next: PresentationStatus) {
+        assert!(
+            self.can_transition_to(next),
+            "Abort. Presentation cannot move from {} to {}",
+            self,
+            next
+        );
+    }
+
+    /// Accepted presentations that are paid out once their end time passes
+    pub fn awaits_settlement(&self) -> bool {
+        matches!(self, PresentationStatus::Signed | PresentationStatus::Running)
+    }
+
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            PresentationStatus::Completed | PresentationStatus::Cancelled | PresentationStatus::Refunded
+        )
+    }
+}
+
+impl fmt::Display for PresentationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = match self {
+            PresentationStatus::Pending => "pending",
+            PresentationStatus::Signed => "signed",
+            PresentationStatus::Running => "running",
+            PresentationStatus::Settling => "settling",
+            PresentationStatus::Completed => "completed",
+            PresentationStatus::Cancelled => "cancelled",
+            PresentationStatus::Refunded => "refunded",
+            PresentationStatus::Disputed => "disputed",
+        };
+        f.write_str(status)
+    }
+}
+
+/// What to book, passed as `msg` of `ft_transfer_call` when paying with a fungible token
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct BookingMessage {
+    /// Assigned by the contract when missing
+    pub playback_id: Option<u64>,
+    pub adspot_id: u64,
+    pub creative_id: u64,
+    pub start_time: Timestamp,
+    pub end_time: Timestamp,
+    pub impressions: Option<u64>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone)]
 #[serde(crate = "near_sdk::serde")]
 pub struct Presentation {
     pub playback_id: u64,
     pub adspot_id: u64,
     pub creative_id: u64,
+    /// The creative version the presentation was booked with, and the hash of its content
+    pub creative_version: u32,
+    pub creative_content_hash: Base64VecU8,
     pub advertiser_cost : Balance,
+    /// The fungible token the presentation was paid with, NEAR when `None`
+    pub token_id: Option<AccountId>,
+    pub pricing: PricingModel,
+    /// Booked impressions of Ad Spots priced per 1000 impressions
+    pub impressions: Option<u64>,
     pub start_time: Timestamp,
     pub end_time: Timestamp,
-    pub transfered: bool,
     pub advertiser_account_id: AccountId,
     pub publisher_account_id: AccountId,
     pub ad_spot_name: String,
@@ -17,23 +118,244 @@ pub struct Presentation {
     pub show_kind: Option<String>,
     pub entertainment: String,
     pub entertainment_fee: Balance,
-    pub status: String,
+    /// Part of the attached deposit kept to pay for storage, on top of `advertiser_cost`.
+    /// Zero when the storage balance of the advertiser paid for it.
+    pub storage_cost: Balance,
+    /// The platform fee in basis points at the time the presentation was signed
+    pub fee_bps: u16,
+    pub status: PresentationStatus,
+    pub cancellation_penalty: Option<u64>,
 }
 
 #[near_bindgen]
 impl MetaAdsContract {
     
+    /// Books the Ad Spot paying with attached NEAR. The price and the storage are kept,
+    /// the rest of the deposit is refunded. The id is assigned by the contract unless `playback_id` is given.
+    /// A creative that references an NFT is booked only once the NFT contract confirms the
+    /// advertiser holds it, so the presentation is the result of the returned promise.
     #[payable]
     pub fn do_agreement(
         &mut self, 
-        playback_id: u64,
+        playback_id: Option<u64>,
         adspot_id: u64, 
         creative_id: u64,  
         start_time: Timestamp, 
         end_time: Timestamp,
-    ) -> Option<Presentation> {
-        
+        impressions: Option<u64>,
+    ) -> PromiseOrValue<Option<Presentation>> {
+        let advertiser_account_id = env::predecessor_account_id();
+        let booking = BookingMessage { playback_id, adspot_id, creative_id, start_time, end_time, impressions };
+
+        if let Some(nft) = self.internal_creative_nft(creative_id) {
+            return PromiseOrValue::Promise(self.internal_book_with_nft(
+                &nft,
+                advertiser_account_id,
+                booking,
+                None,
+                env::attached_deposit(),
+            ));
+        }
+
+        let (presentation, unused) =
+            self.internal_do_agreement(advertiser_account_id, booking, None, env::attached_deposit());
+        refund_unused_deposit(unused);
+
+        PromiseOrValue::Value(Some(presentation))
+    }
+
+    pub fn cancel_presentation(&mut self, playback_id: u64) -> Presentation {
+        assert!(playback_id > 0, "Abort. Presentation Id undefined");
+
+        let mut presentation = self.presentations.get(&playback_id).expect("Presentation not found");
+        assert_eq!(
+            presentation.advertiser_account_id,
+            env::predecessor_account_id(),
+            "Abort. Only the advertiser can cancel the presentation"
+        );
+        presentation.status.assert_can_transition_to(PresentationStatus::Cancelled);
+
+        let time: u64 = env::block_timestamp() / 1000000000;
+        assert!(time < presentation.start_time, "Abort. Presentation has already started");
+
+        // A pending presentation was never binding for the publisher, so it is refunded in full
+        let penalty: Balance = if presentation.status == PresentationStatus::Signed {
+            presentation.advertiser_cost
+                * Balance::from(presentation.cancellation_penalty.unwrap_or(0))
+                / 100
+        } else {
+            0
+        };
+        let refund: Balance = presentation.advertiser_cost - penalty;
+        self.internal_treasury_mut(&presentation.token_id, |treasury| {
+            treasury.release_escrow(presentation.advertiser_cost)
+        });
+
+        if refund > 0 {
+            self.internal_payout(&presentation.advertiser_account_id, refund, &presentation.token_id);
+        }
+        if penalty > 0 {
+            self.internal_payout(&presentation.publisher_account_id, penalty, &presentation.token_id);
+        }
+
+        log!(
+            "The advertiser {} cancelled presentation {}. Refunded {}, penalty {}",
+            presentation.advertiser_account_id, playback_id, refund, penalty
+        );
+
+        EventLog::new(EventLogVariant::PresentationCancelled(vec![PresentationCancelledLog {
+            playback_id,
+            advertiser_id: presentation.advertiser_account_id.clone(),
+            refund: U128(refund),
+            penalty: U128(penalty),
+            token_id: presentation.token_id.clone(),
+        }]))
+        .emit();
+
+        self.internal_set_presentation_status(&mut presentation, PresentationStatus::Cancelled);
+
+        presentation
+    }
+
+    pub fn accept_presentation(&mut self, playback_id: u64) -> Presentation {
+        let mut presentation = self.internal_pending_presentation_for_publisher(playback_id);
+
+        let time: u64 = env::block_timestamp() / 1000000000;
+        assert!(time < presentation.start_time, "Abort. Acceptance deadline has passed");
+
+        self.internal_set_presentation_status(&mut presentation, PresentationStatus::Signed);
+
+        presentation
+    }
+
+    pub fn reject_presentation(&mut self, playback_id: u64) -> Presentation {
+        let mut presentation = self.internal_pending_presentation_for_publisher(playback_id);
+
+        self.internal_refund_presentation(&presentation);
+
+        log!(
+            "The publisher {} rejected presentation {}. Refunded {} to {}",
+            presentation.publisher_account_id, playback_id, presentation.advertiser_cost, presentation.advertiser_account_id
+        );
+
+        self.internal_set_presentation_status(&mut presentation, PresentationStatus::Refunded);
+
+        presentation
+    }
+
+    /// Refunds a presentation the publisher did not accept before its start time.
+    /// Anyone can call it once the deadline has passed.
+    pub fn refund_expired_presentation(&mut self, playback_id: u64) -> Presentation {
+        assert!(playback_id > 0, "Abort. Presentation Id undefined");
+
+        let mut presentation = self.presentations.get(&playback_id).expect("Presentation not found");
+        assert!(presentation.status == PresentationStatus::Pending, "Abort. Presentation is {}", presentation.status);
+
+        let time: u64 = env::block_timestamp() / 1000000000;
+        assert!(presentation.start_time <= time, "Abort. Acceptance deadline has not passed yet");
+
+        self.internal_refund_presentation(&presentation);
+
+        log!(
+            "Presentation {} expired without an answer. Refunded {} to {}",
+            playback_id, presentation.advertiser_cost, presentation.advertiser_account_id
+        );
+
+        self.internal_set_presentation_status(&mut presentation, PresentationStatus::Refunded);
+
+        presentation
+    }
+
+    pub fn fetch_presentation_by_id(&self, id: u64) -> Presentation {
+        self.presentations.get(&id).expect("Presentation not found")
+    }
+}
+
+impl MetaAdsContract {
+    /// Creates the presentation paid with `amount` of `token_id` (NEAR when `None`).
+    /// NEAR payments also cover the storage, token payments leave it to the storage balance.
+    /// Returns the presentation and the part of `amount` that was not needed.
+    pub(crate) fn internal_do_agreement(
+        &mut self,
+        advertiser_account_id: AccountId,
+        booking: BookingMessage,
+        token_id: Option<AccountId>,
+        amount: Balance,
+    ) -> (Presentation, Balance) {
+        let (adspot, creative, required) = self.internal_validate_booking(&advertiser_account_id, &booking, &token_id, amount);
+        let BookingMessage { playback_id, adspot_id, creative_id, start_time, end_time, impressions } = booking;
+
+        let playback_id = next_id(&mut self.next_playback_id, &mut self.taken_playback_ids, playback_id);
         assert!(playback_id > 0, "Abort. Playback Id undefined");
+
+        let fee_bps = self.internal_fee_for_adspot(&adspot);
+        let fee = fee_from_bps(required, fee_bps);
+
+        let storage_before = env::storage_usage();
+
+        let mut presentation = Presentation {
+            playback_id,
+            adspot_id,
+            creative_id,
+            creative_version: creative.version,
+            creative_content_hash: creative.content_hash,
+            advertiser_cost: required,
+            token_id,
+            pricing: adspot.pricing,
+            impressions,
+            start_time,
+            end_time,
+            advertiser_account_id: advertiser_account_id.clone(),
+            publisher_account_id: adspot.owner_account_id.clone(),
+            ad_spot_name: adspot.name.clone(),
+            publisher_earn: adspot.publisher_earn,
+            show_kind: adspot.show_kind.clone(),
+            entertainment: env::current_account_id(),
+            entertainment_fee: fee,
+            storage_cost: 0,
+            fee_bps,
+            status: PresentationStatus::Pending,
+            cancellation_penalty: adspot.cancellation_penalty,
+        };
+
+        assert!(
+            self.presentations.insert(&playback_id, &presentation).is_none(),
+            "Presentation already exists"
+        );
+        self.internal_treasury_mut(&presentation.token_id, |treasury| treasury.escrowed += required);
+        self.internal_add_booking(&presentation);
+        self.internal_index_presentation(&presentation);
+
+        let unused = if presentation.token_id.is_none() {
+            let spare = amount - required;
+            let unused = self.internal_update_storage(&advertiser_account_id, storage_before, spare);
+            // The storage cost is a fixed size field, writing it back does not change the usage
+            presentation.storage_cost = spare - unused;
+            self.presentations.insert(&playback_id, &presentation);
+            unused
+        } else {
+            self.internal_update_storage(&advertiser_account_id, storage_before, 0);
+            amount - required
+        };
+        EventLog::new(EventLogVariant::PresentationSigned(vec![presentation.clone()])).emit();
+
+        (presentation, unused)
+    }
+
+    /// Checks everything a booking needs except the playback id.
+    /// Returns the Ad Spot, the creative and the amount the booking costs.
+    pub(crate) fn internal_validate_booking(
+        &self,
+        advertiser_account_id: &AccountId,
+        booking: &BookingMessage,
+        token_id: &Option<AccountId>,
+        amount: Balance,
+    ) -> (AdSpot, Creative, Balance) {
+        let BookingMessage { playback_id, adspot_id, creative_id, start_time, end_time, impressions } = booking.clone();
+
+        if let Some(playback_id) = playback_id {
+            assert!(self.presentations.get(&playback_id).is_none(), "Presentation already exists");
+        }
         assert!(adspot_id > 0, "Abort. AdSpace Id undefined");
         assert!(creative_id > 0, "Abort. Creative Id undefined");
 
@@ -42,57 +364,237 @@ impl MetaAdsContract {
         assert!(end_time > time, "Abort. End time is less than current time");
         assert!(end_time > start_time, "Abort. Start time must be less than End time");
 
-        if let Some(creative) = self.creatives.get(&creative_id) {
-
-            if let Some(adspot) = self.ad_spots.get(&adspot_id) {
-
-                let deposit: Balance = env::attached_deposit();
-                assert!(deposit >= adspot.price, "Deposit is too small. Attached: {}, Required: {}", deposit, adspot.price);
-
-                let advertiser_account_id = env::predecessor_account_id();
-                let owner_account_id = creative.owner_account_id.clone();
-                assert_eq!(owner_account_id, advertiser_account_id, "Abort. Creative not available. Wrong account");
-
-                let fee = deposit / 10;
-
-                let presentation = Presentation {
-                    playback_id,
-                    adspot_id,
-                    creative_id,
-                    advertiser_cost: deposit.into(),
-                    start_time,
-                    end_time,
-                    transfered: false,
-                    advertiser_account_id: advertiser_account_id.clone(),
-                    publisher_account_id: adspot.owner_account_id.clone(),
-                    ad_spot_name: adspot.name.clone(),
-                    publisher_earn: adspot.publisher_earn.clone(),
-                    show_kind: adspot.show_kind.clone(),
-                    entertainment: env::current_account_id(),
-                    entertainment_fee: fee,
-                    status: String::from("signed")
-                };
-
-                assert!(
-                    self.presentations.insert(&playback_id, &presentation).is_none(),
-                    "Presentation already exists"
-                );
-                
-                Some(presentation)
-
-            } else {
-                near_sdk::env::panic(b"Ad Spot not found");
-            }    
-        } else {
-            near_sdk::env::panic(b"Creative not found");
+        let creative = self.creatives.get(&creative_id).unwrap_or_else(|| env::panic(b"Creative not found"));
+        let adspot = self.ad_spots.get(&adspot_id).unwrap_or_else(|| env::panic(b"Ad Spot not found"));
+
+        assert!(adspot.listed, "Abort. Ad Spot is delisted");
+        assert!(!creative.archived, "Abort. Creative is archived");
+        assert_creative_allowed_on(&creative, &adspot);
+        assert_creative_format_accepted(&creative, &adspot);
+        self.internal_assert_window_free(&adspot, start_time, end_time);
+
+        let price: Balance = adspot.price_in(token_id);
+        let required: Balance = adspot.pricing.required_amount(price, start_time, end_time, impressions);
+        assert!(amount >= required, "Deposit is too small. Attached: {}, Required: {}", amount, required);
+
+        assert_eq!(&creative.owner_account_id, advertiser_account_id, "Abort. Creative not available. Wrong account");
+
+        (adspot, creative, required)
+    }
+
+    fn internal_pending_presentation_for_publisher(&self, playback_id: u64) -> Presentation {
+        assert!(playback_id > 0, "Abort. Presentation Id undefined");
+
+        let presentation = self.presentations.get(&playback_id).expect("Presentation not found");
+        let adspot = self.ad_spots.get(&presentation.adspot_id).expect("Ad Spot not found");
+        assert_eq!(
+            adspot.owner_account_id,
+            env::predecessor_account_id(),
+            "Abort. Only the Ad Spot owner can answer the presentation"
+        );
+        assert!(presentation.status == PresentationStatus::Pending, "Abort. Presentation is {}", presentation.status);
+
+        presentation
+    }
+
+    /// Gives the whole cost back to the advertiser
+    fn internal_refund_presentation(&mut self, presentation: &Presentation) {
+        self.internal_treasury_mut(&presentation.token_id, |treasury| {
+            treasury.release_escrow(presentation.advertiser_cost)
+        });
+        self.internal_payout(&presentation.advertiser_account_id, presentation.advertiser_cost, &presentation.token_id);
+    }
+
+    /// The only place where a stored presentation changes its status
+    pub(crate) fn internal_set_presentation_status(
+        &mut self,
+        presentation: &mut Presentation,
+        status: PresentationStatus,
+    ) {
+        presentation.status.assert_can_transition_to(status);
+        let old_status = presentation.status;
+        presentation.status = status;
+        self.presentations.insert(&presentation.playback_id, presentation);
+        if status.awaits_settlement() || old_status.awaits_settlement() {
+            self.internal_update_open_presentations(presentation);
+        }
+        EventLog::new(EventLogVariant::PresentationStatusChanged(vec![PresentationStatusLog {
+            playback_id: presentation.playback_id,
+            old_status,
+            new_status: status,
+        }]))
+        .emit();
+
+        // Cancelled and refunded presentations free their window for new bookings
+        if matches!(status, PresentationStatus::Cancelled | PresentationStatus::Refunded) {
+            self.internal_remove_booking(presentation);
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use PresentationStatus::*;
 
-    pub fn fetch_all_presentations(&self) -> HashMap<u64, Presentation> {
-        self.presentations.iter().collect()
+    /// An Ad Spot of publisher.near costing `SPOT_NEAR` and a creative of advertiser.near
+    fn setup(cancellation_penalty: Option<u64>) -> (MetaAdsContract, u64, u64) {
+        set_caller("publisher.near", NEAR, 0);
+        let mut contract = new_contract();
+        let adspot = contract.make_adspot(None, 1, "Banner".to_string(), None, None, cancellation_penalty, None, None, None, None);
+
+        set_caller("advertiser.near", NEAR, 0);
+        let creative = make_creative(&mut contract, "ipfs://v1");
+        (contract, adspot.adspot_id, creative.creative_id)
     }
 
-    pub fn fetch_presentation_by_id(&self, id: u64) -> Presentation {
-        self.presentations.get(&id).expect("Presentation not found")
+    /// Books the window from 100 to 200 as advertiser.near
+    fn book(contract: &mut MetaAdsContract, adspot_id: u64, creative_id: u64) -> Presentation {
+        set_caller("advertiser.near", NEAR, 0);
+        match contract.do_agreement(None, adspot_id, creative_id, 100, 200, None) {
+            PromiseOrValue::Value(Some(presentation)) => presentation,
+            _ => panic!("presentation not booked"),
+        }
     }
-}  
\ No newline at end of file
+
+    fn storage_total(contract: &MetaAdsContract, account_id: &str) -> Balance {
+        contract.storage_accounts.get(&account_id.to_string()).unwrap().total
+    }
+
+    #[test]
+    fn storage_cost_is_the_part_of_the_deposit_kept_for_storage() {
+        let (mut contract, adspot_id, creative_id) = setup(None);
+        let total_before = storage_total(&contract, "advertiser.near");
+
+        let presentation = book(&mut contract, adspot_id, creative_id);
+
+        assert!(presentation.storage_cost > 0);
+        assert_eq!(presentation.storage_cost, storage_total(&contract, "advertiser.near") - total_before);
+        assert_eq!(contract.fetch_presentation_by_id(1).storage_cost, presentation.storage_cost);
+    }
+
+    #[test]
+    fn storage_cost_is_zero_when_the_storage_balance_pays() {
+        let (mut contract, adspot_id, creative_id) = setup(None);
+        set_caller("advertiser.near", NEAR, 0);
+        contract.storage_deposit(None, None);
+
+        let presentation = book(&mut contract, adspot_id, creative_id);
+
+        assert_eq!(presentation.storage_cost, 0);
+    }
+
+    fn cancelled_log() -> String {
+        near_sdk::test_utils::get_logs()
+            .into_iter()
+            .find(|log| log.contains("\"event\":\"presentation_cancelled\""))
+            .expect("no presentation_cancelled event")
+    }
+
+    #[test]
+    fn cancelling_a_signed_presentation_pays_the_penalty() {
+        let (mut contract, adspot_id, creative_id) = setup(Some(30));
+        book(&mut contract, adspot_id, creative_id);
+        set_caller("publisher.near", 0, 10);
+        contract.accept_presentation(1);
+
+        set_caller("advertiser.near", 0, 50);
+        let cancelled = contract.cancel_presentation(1);
+
+        assert_eq!(cancelled.status, Cancelled);
+        let log = cancelled_log();
+        assert!(log.contains(&format!("\"refund\":\"{}\"", SPOT_NEAR * 70 / 100)));
+        assert!(log.contains(&format!("\"penalty\":\"{}\"", SPOT_NEAR * 30 / 100)));
+        assert_eq!(contract.fetch_treasury(None).escrowed, 0);
+        assert!(contract.fetch_adspot_schedule(adspot_id, 0, 300).iter().all(|window| window.playback_id.is_none()));
+    }
+
+    #[test]
+    fn cancelling_a_pending_presentation_refunds_everything() {
+        let (mut contract, adspot_id, creative_id) = setup(Some(30));
+        book(&mut contract, adspot_id, creative_id);
+
+        set_caller("advertiser.near", 0, 50);
+        contract.cancel_presentation(1);
+
+        let log = cancelled_log();
+        assert!(log.contains(&format!("\"refund\":\"{}\"", SPOT_NEAR)));
+        assert!(log.contains("\"penalty\":\"0\""));
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Presentation has already started")]
+    fn cancelling_after_the_start() {
+        let (mut contract, adspot_id, creative_id) = setup(None);
+        book(&mut contract, adspot_id, creative_id);
+
+        set_caller("advertiser.near", 0, 100);
+        contract.cancel_presentation(1);
+    }
+
+    #[test]
+    fn rejected_presentation_is_refunded() {
+        let (mut contract, adspot_id, creative_id) = setup(None);
+        book(&mut contract, adspot_id, creative_id);
+        assert_eq!(contract.fetch_treasury(None).escrowed, SPOT_NEAR);
+
+        set_caller("publisher.near", 0, 10);
+        assert_eq!(contract.reject_presentation(1).status, Refunded);
+
+        assert_eq!(contract.fetch_treasury(None).escrowed, 0);
+        assert_eq!(near_sdk::test_utils::get_created_receipts().len(), 2);
+        book(&mut contract, adspot_id, creative_id);
+    }
+
+    #[test]
+    fn unanswered_presentation_is_refunded_after_the_start() {
+        let (mut contract, adspot_id, creative_id) = setup(None);
+        book(&mut contract, adspot_id, creative_id);
+
+        set_caller("anyone.near", 0, 100);
+        assert_eq!(contract.refund_expired_presentation(1).status, Refunded);
+        assert_eq!(contract.fetch_treasury(None).escrowed, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Acceptance deadline has not passed yet")]
+    fn unanswered_presentation_before_the_start() {
+        let (mut contract, adspot_id, creative_id) = setup(None);
+        book(&mut contract, adspot_id, creative_id);
+
+        set_caller("anyone.near", 0, 99);
+        contract.refund_expired_presentation(1);
+    }
+
+    #[test]
+    fn terminal_statuses_have_no_transitions() {
+        let all = [Pending, Signed, Running, Settling, Completed, Cancelled, Refunded, Disputed];
+        for status in all.iter().filter(|status| status.is_terminal()) {
+            assert!(all.iter().all(|next| !status.can_transition_to(*next)));
+        }
+    }
+
+    #[test]
+    fn pending_must_be_accepted_before_completion() {
+        assert!(!Pending.can_transition_to(Completed));
+        assert!(!Pending.can_transition_to(Running));
+        assert!(Pending.can_transition_to(Signed));
+        assert!(Signed.can_transition_to(Settling));
+        assert!(Settling.can_transition_to(Completed));
+        assert!(!Completed.can_transition_to(Cancelled));
+    }
+
+    #[test]
+    fn disputed_is_never_settled() {
+        assert!(!Disputed.can_transition_to(Settling));
+        assert!(!Disputed.awaits_settlement());
+        assert!(Disputed.can_transition_to(Refunded));
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Presentation cannot move from cancelled to signed")]
+    fn illegal_transition_panics() {
+        Cancelled.assert_can_transition_to(Signed);
+    }
+}
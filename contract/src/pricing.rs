@@ -0,0 +1,68 @@
+use crate::*;
+
+const SECONDS_PER_HOUR: u64 = 60 * 60;
+const SECONDS_PER_DAY: u64 = 24 * SECONDS_PER_HOUR;
+
+/// How `AdSpot.price` is charged
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug, Default)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum PricingModel {
+    /// The price covers the whole booking, however long it is
+    #[default]
+    Flat,
+    /// The price of every started hour
+    PerHour,
+    /// The price of every started day
+    PerDay,
+    /// The price of every started 1000 impressions
+    PerThousandImpressions,
+}
+
+impl PricingModel {
+    /// The amount a booking from `start_time` to `end_time` costs at `price`
+    pub fn required_amount(
+        &self,
+        price: Balance,
+        start_time: Timestamp,
+        end_time: Timestamp,
+        impressions: Option<u64>,
+    ) -> Balance {
+        let units: u64 = match self {
+            PricingModel::Flat => 1,
+            PricingModel::PerHour => (end_time - start_time).div_ceil(SECONDS_PER_HOUR),
+            PricingModel::PerDay => (end_time - start_time).div_ceil(SECONDS_PER_DAY),
+            PricingModel::PerThousandImpressions => {
+                let impressions = impressions.expect("Abort. Impressions are required by this Ad Spot");
+                assert!(impressions > 0, "Abort. Impressions undefined");
+                impressions.div_ceil(1000)
+            }
+        };
+        price.checked_mul(Balance::from(units)).expect("Abort. Price overflow")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_ignores_duration() {
+        assert_eq!(PricingModel::Flat.required_amount(10, 0, 60, None), 10);
+        assert_eq!(PricingModel::Flat.required_amount(10, 0, 30 * SECONDS_PER_DAY, None), 10);
+    }
+
+    #[test]
+    fn started_units_are_charged() {
+        assert_eq!(PricingModel::PerHour.required_amount(10, 0, 60, None), 10);
+        assert_eq!(PricingModel::PerHour.required_amount(10, 0, SECONDS_PER_HOUR + 1, None), 20);
+        assert_eq!(PricingModel::PerDay.required_amount(10, 100, 100 + 3 * SECONDS_PER_DAY, None), 30);
+        assert_eq!(PricingModel::PerThousandImpressions.required_amount(10, 0, 60, Some(2500)), 30);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Impressions are required by this Ad Spot")]
+    fn impressions_are_required() {
+        PricingModel::PerThousandImpressions.required_amount(10, 0, 60, None);
+    }
+}
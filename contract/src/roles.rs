@@ -0,0 +1,243 @@
+use crate::*;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can do everything the owner can, except transferring the ownership and managing admins
+    Admin,
+    Moderator,
+    /// Can settle any presentation whose show time is over
+    Keeper,
+    /// Can change the platform fee and its overrides
+    FeeManager,
+}
+
+#[near_bindgen]
+impl MetaAdsContract {
+
+    /// Role changes, like every owner and fee setting, require 1 yoctoNEAR attached
+    #[payable]
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) -> bool {
+        assert_one_yocto();
+        self.assert_can_manage_role(role);
+        assert!(env::is_valid_account_id(account_id.as_bytes()), "Abort. Invalid account id");
+
+        let mut roles = self.roles.get(&account_id).unwrap_or_default();
+        if roles.contains(&role) {
+            return false;
+        }
+        roles.push(role);
+        self.roles.insert(&account_id, &roles);
+        EventLog::new(EventLogVariant::RoleGranted(vec![RoleLog { account_id, role }])).emit();
+        true
+    }
+
+    #[payable]
+    pub fn revoke_role(&mut self, account_id: AccountId, role: Role) -> bool {
+        assert_one_yocto();
+        self.assert_can_manage_role(role);
+
+        let mut roles = self.roles.get(&account_id).unwrap_or_default();
+        if !roles.contains(&role) {
+            return false;
+        }
+        roles.retain(|granted| *granted != role);
+        if roles.is_empty() {
+            self.roles.remove(&account_id);
+        } else {
+            self.roles.insert(&account_id, &roles);
+        }
+        EventLog::new(EventLogVariant::RoleRevoked(vec![RoleLog { account_id, role }])).emit();
+        true
+    }
+
+    /// First step of the ownership transfer, the new owner has to call `accept_ownership`
+    #[payable]
+    pub fn transfer_ownership(&mut self, new_owner_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(env::is_valid_account_id(new_owner_id.as_bytes()), "Abort. Invalid account id");
+        EventLog::new(EventLogVariant::OwnershipTransferStarted(vec![OwnershipLog {
+            old_owner_id: self.owner_id.clone(),
+            new_owner_id: new_owner_id.clone(),
+        }]))
+        .emit();
+        self.pending_owner_id = Some(new_owner_id);
+    }
+
+    #[payable]
+    pub fn accept_ownership(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        assert_eq!(
+            self.pending_owner_id.as_ref(),
+            Some(&account_id),
+            "Abort. Only the pending owner can accept the ownership"
+        );
+        log!("Ownership transferred from {} to {}", self.owner_id, account_id);
+        EventLog::new(EventLogVariant::OwnershipTransferred(vec![OwnershipLog {
+            old_owner_id: self.owner_id.clone(),
+            new_owner_id: account_id.clone(),
+        }]))
+        .emit();
+        self.owner_id = account_id;
+        self.pending_owner_id = None;
+    }
+
+    pub fn fetch_owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    pub fn fetch_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner_id.clone()
+    }
+
+    pub fn fetch_roles(&self, account_id: AccountId) -> Vec<Role> {
+        self.roles.get(&account_id).unwrap_or_default()
+    }
+
+    pub fn fetch_role_members(&self, role: Role) -> Vec<AccountId> {
+        self.roles
+            .iter()
+            .filter(|(_, roles)| roles.contains(&role))
+            .map(|(account_id, _)| account_id)
+            .collect()
+    }
+}
+
+impl MetaAdsContract {
+    pub(crate) fn assert_owner(&self) {
+        assert_eq!(
+            self.owner_id,
+            env::predecessor_account_id(),
+            "Abort. Only the contract owner can do this"
+        );
+    }
+
+    /// The owner and admins pass every role check
+    pub(crate) fn has_role(&self, account_id: &AccountId, role: Role) -> bool {
+        if account_id == &self.owner_id {
+            return true;
+        }
+        let roles = self.roles.get(account_id).unwrap_or_default();
+        roles.contains(&Role::Admin) || roles.contains(&role)
+    }
+
+    pub(crate) fn assert_role(&self, role: Role) {
+        assert!(
+            self.has_role(&env::predecessor_account_id(), role),
+            "Abort. Requires the {:?} role",
+            role
+        );
+    }
+
+    fn assert_can_manage_role(&self, role: Role) {
+        if role == Role::Admin {
+            self.assert_owner();
+        } else {
+            self.assert_role(Role::Admin);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    /// owner.near made admin.near an Admin
+    fn setup() -> MetaAdsContract {
+        set_caller("owner.near", 1, 0);
+        let mut contract = new_contract();
+        assert!(contract.grant_role("admin.near".to_string(), Role::Admin));
+        contract
+    }
+
+    #[test]
+    fn admins_manage_the_other_roles() {
+        let mut contract = setup();
+        set_caller("admin.near", 1, 0);
+        assert!(contract.grant_role("keeper.near".to_string(), Role::Keeper));
+        assert!(!contract.grant_role("keeper.near".to_string(), Role::Keeper));
+
+        let keeper = "keeper.near".to_string();
+        assert!(contract.has_role(&keeper, Role::Keeper));
+        assert!(!contract.has_role(&keeper, Role::Moderator));
+        assert!(contract.has_role(&"admin.near".to_string(), Role::Moderator));
+        assert!(contract.has_role(&"owner.near".to_string(), Role::Keeper));
+        assert!(!contract.has_role(&"someone.near".to_string(), Role::Keeper));
+        assert_eq!(contract.fetch_role_members(Role::Keeper), vec![keeper]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Only the contract owner can do this")]
+    fn admins_cannot_manage_admins() {
+        let mut contract = setup();
+        set_caller("admin.near", 1, 0);
+        contract.grant_role("someone.near".to_string(), Role::Admin);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Requires the Admin role")]
+    fn others_cannot_manage_roles() {
+        let mut contract = setup();
+        set_caller("moderator.near", 1, 0);
+        contract.grant_role("moderator.near".to_string(), Role::Moderator);
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+    fn role_changes_need_one_yocto() {
+        let mut contract = setup();
+        set_caller("owner.near", 0, 0);
+        contract.grant_role("keeper.near".to_string(), Role::Keeper);
+    }
+
+    #[test]
+    fn revoked_roles_no_longer_pass() {
+        let mut contract = setup();
+        contract.grant_role("admin.near".to_string(), Role::Keeper);
+
+        assert!(contract.revoke_role("admin.near".to_string(), Role::Admin));
+        assert!(!contract.revoke_role("admin.near".to_string(), Role::Admin));
+        assert_eq!(contract.fetch_roles("admin.near".to_string()), vec![Role::Keeper]);
+        assert!(!contract.has_role(&"admin.near".to_string(), Role::Moderator));
+
+        contract.revoke_role("admin.near".to_string(), Role::Keeper);
+        assert!(contract.fetch_roles("admin.near".to_string()).is_empty());
+        assert!(contract.roles.get(&"admin.near".to_string()).is_none());
+    }
+
+    #[test]
+    fn ownership_moves_once_the_new_owner_accepts() {
+        let mut contract = setup();
+        contract.transfer_ownership("new-owner.near".to_string());
+        assert_eq!(contract.fetch_owner(), "owner.near".to_string());
+        assert_eq!(contract.fetch_pending_owner(), Some("new-owner.near".to_string()));
+
+        set_caller("new-owner.near", 1, 0);
+        contract.accept_ownership();
+        assert_eq!(contract.fetch_owner(), "new-owner.near".to_string());
+        assert_eq!(contract.fetch_pending_owner(), None);
+        assert!(!contract.has_role(&"owner.near".to_string(), Role::Keeper));
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Only the pending owner can accept the ownership")]
+    fn ownership_accepted_by_another_account() {
+        let mut contract = setup();
+        contract.transfer_ownership("new-owner.near".to_string());
+
+        set_caller("admin.near", 1, 0);
+        contract.accept_ownership();
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Only the contract owner can do this")]
+    fn only_the_owner_transfers_the_ownership() {
+        let mut contract = setup();
+        set_caller("admin.near", 1, 0);
+        contract.transfer_ownership("admin.near".to_string());
+    }
+}
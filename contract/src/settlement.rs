@@ -0,0 +1,276 @@
+use crate::*;
+
+/// Keys are (end_time, playback_id) so due presentations come first. Entries leave the
+/// index once the presentation is settled, so the contract covers their storage.
+pub type OpenPresentations = TreeMap<(Timestamp, u64), ()>;
+
+#[near_bindgen]
+impl MetaAdsContract {
+
+    /// Pays the publisher once the show is over. Callable by the publisher,
+    /// the advertiser or a keeper.
+    pub fn transfer_funds(&mut self, playback_id: u64) -> PromiseOrValue<bool> {
+
+        assert!(playback_id > 0, "Abort. Presentation Id undefined");
+
+        match self.presentations.get(&playback_id) {
+            Some(presentation) => {
+                let caller = env::predecessor_account_id();
+                assert!(
+                    caller == presentation.publisher_account_id
+                        || caller == presentation.advertiser_account_id
+                        || self.has_role(&caller, Role::Keeper),
+                    "Abort. Only the publisher, the advertiser or a keeper can settle the presentation"
+                );
+
+                PromiseOrValue::Promise(self.internal_settle(presentation))
+            }
+            None => PromiseOrValue::Value(false),
+        }
+    }
+
+    /// Settles up to `limit` presentations whose show time is over. Keepers only.
+    pub fn settle_due_presentations(&mut self, limit: u64) -> Vec<u64> {
+        self.assert_role(Role::Keeper);
+
+        let time: u64 = env::block_timestamp() / 1000000000;
+        let due: Vec<u64> = self
+            .open_presentations
+            .iter()
+            .take_while(|((end_time, _), _)| *end_time <= time)
+            .take(limit as usize)
+            .map(|((_, playback_id), _)| playback_id)
+            .collect();
+
+        for playback_id in due.iter() {
+            let presentation = self.presentations.get(playback_id).unwrap();
+            self.internal_settle(presentation);
+        }
+
+        due
+    }
+
+    #[private]
+    pub fn on_transfer_funds(&mut self, playback_id: u64) -> bool {
+        assert_eq!(env::promise_results_count(), 1, "Abort. Expected one promise result");
+
+        let mut presentation = self.presentations.get(&playback_id).expect("Presentation not found");
+        let total_funds: Balance = presentation.advertiser_cost - presentation.entertainment_fee;
+        let token_id = presentation.token_id.clone();
+
+        let delivered = matches!(env::promise_result(0), PromiseResult::Successful(_));
+        EventLog::new(EventLogVariant::PresentationSettled(vec![PresentationSettledLog {
+            playback_id,
+            publisher_id: presentation.publisher_account_id.clone(),
+            amount: U128(total_funds),
+            fee: U128(presentation.entertainment_fee),
+            delivered,
+            token_id: token_id.clone(),
+        }]))
+        .emit();
+
+        if delivered {
+            log!("The publisher {} received funds in the amount of {}", presentation.publisher_account_id, total_funds);
+        } else {
+            // The funds came back to the contract, keep them for the publisher to withdraw later
+            log!(
+                "Transfer of {} to the publisher {} failed. The amount can be claimed with withdraw_claimable",
+                total_funds, presentation.publisher_account_id
+            );
+            self.internal_add_claimable(&presentation.publisher_account_id, total_funds, &token_id);
+        }
+        self.internal_set_presentation_status(&mut presentation, PresentationStatus::Completed);
+
+        delivered
+    }
+
+    /// Withdraws the payouts in NEAR, or in `token_id`, that could not be delivered to the caller before
+    pub fn withdraw_claimable(&mut self, token_id: Option<AccountId>) -> Promise {
+        let account_id = env::predecessor_account_id();
+        let amount = self
+            .claimable_balances
+            .remove(&(account_id.clone(), token_id.clone()))
+            .unwrap_or(0);
+        assert!(amount > 0, "Abort. Nothing to claim");
+        self.internal_treasury_mut(&token_id, |treasury| treasury.claimable -= amount);
+        EventLog::new(EventLogVariant::ClaimableWithdrawn(vec![BalanceLog {
+            account_id: account_id.clone(),
+            amount: U128(amount),
+            token_id: token_id.clone(),
+        }]))
+        .emit();
+
+        self.internal_transfer(&account_id, amount, &token_id)
+            .then(ext_self::on_withdraw_claimable(
+                account_id,
+                amount,
+                token_id,
+                &env::current_account_id(),
+                0,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ))
+    }
+
+    #[private]
+    pub fn on_withdraw_claimable(&mut self, account_id: AccountId, amount: Balance, token_id: Option<AccountId>) -> bool {
+        assert_eq!(env::promise_results_count(), 1, "Abort. Expected one promise result");
+
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => {
+                log!("The account {} claimed {}", account_id, amount);
+                true
+            }
+            _ => {
+                self.internal_add_claimable(&account_id, amount, &token_id);
+                false
+            }
+        }
+    }
+
+    pub fn fetch_claimable_balance(&self, account_id: AccountId, token_id: Option<AccountId>) -> Balance {
+        self.claimable_balances.get(&(account_id, token_id)).unwrap_or(0)
+    }
+}
+
+impl MetaAdsContract {
+    pub(crate) fn internal_add_claimable(&mut self, account_id: &AccountId, amount: Balance, token_id: &Option<AccountId>) {
+        let key = (account_id.clone(), token_id.clone());
+        let balance = self.claimable_balances.get(&key).unwrap_or(0);
+        self.claimable_balances.insert(&key, &(balance + amount));
+        self.internal_treasury_mut(token_id, |treasury| treasury.claimable += amount);
+        EventLog::new(EventLogVariant::ClaimableAdded(vec![BalanceLog {
+            account_id: account_id.clone(),
+            amount: U128(amount),
+            token_id: token_id.clone(),
+        }]))
+        .emit();
+    }
+
+    /// Keeps `open_presentations` in line with the status of the presentation
+    pub(crate) fn internal_update_open_presentations(&mut self, presentation: &Presentation) {
+        let key = (presentation.end_time, presentation.playback_id);
+        if presentation.status.awaits_settlement() {
+            self.open_presentations.insert(&key, &());
+        } else {
+            self.open_presentations.remove(&key);
+        }
+    }
+
+    fn internal_settle(&mut self, mut presentation: Presentation) -> Promise {
+        let time: u64 = env::block_timestamp() / 1000000000;
+        assert!(presentation.end_time <= time, "Abort. Presentation is active. Show time is not over yet");
+
+        self.internal_set_presentation_status(&mut presentation, PresentationStatus::Settling);
+
+        let total_funds: Balance = presentation.advertiser_cost - presentation.entertainment_fee;
+        self.internal_treasury_mut(&presentation.token_id, |treasury| {
+            treasury.release_escrow(presentation.advertiser_cost);
+            treasury.fees_earned += presentation.entertainment_fee;
+        });
+
+        self.internal_transfer(&presentation.publisher_account_id, total_funds, &presentation.token_id)
+            .then(ext_self::on_transfer_funds(
+                presentation.playback_id,
+                &env::current_account_id(),
+                0,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+
+    /// Books the given windows of one Ad Spot, all of them accepted by the publisher
+    fn setup_signed(windows: &[(Timestamp, Timestamp)]) -> MetaAdsContract {
+        set_caller("publisher.near", NEAR, 0);
+        let mut contract = new_contract();
+        let adspot = contract.make_adspot(None, 1, "Banner".to_string(), None, None, None, None, None, None, None);
+
+        set_caller("advertiser.near", NEAR, 0);
+        let creative = make_creative(&mut contract, "ipfs://v1");
+        for (start_time, end_time) in windows.iter() {
+            contract.do_agreement(None, adspot.adspot_id, creative.creative_id, *start_time, *end_time, None);
+        }
+
+        set_caller("publisher.near", 0, 0);
+        for playback_id in 1..=windows.len() as u64 {
+            contract.accept_presentation(playback_id);
+        }
+        contract
+    }
+
+    #[test]
+    fn keeper_settles_due_presentations_in_end_time_order() {
+        let mut contract = setup_signed(&[(300, 400), (100, 200), (500, 600)]);
+        assert_eq!(contract.open_presentations.len(), 3);
+
+        set_caller("owner.near", 0, 450);
+        assert_eq!(contract.settle_due_presentations(1), vec![2]);
+        assert_eq!(contract.settle_due_presentations(10), vec![1]);
+        assert!(contract.settle_due_presentations(10).is_empty());
+
+        assert_eq!(contract.fetch_presentation_by_id(1).status, PresentationStatus::Settling);
+        assert_eq!(contract.open_presentations.to_vec(), vec![((600, 3), ())]);
+    }
+
+    #[test]
+    fn cancelled_presentations_leave_the_index() {
+        let mut contract = setup_signed(&[(100, 200)]);
+
+        set_caller("advertiser.near", 0, 50);
+        contract.cancel_presentation(1);
+
+        assert_eq!(contract.open_presentations.len(), 0);
+    }
+
+    #[test]
+    fn failed_payout_becomes_claimable() {
+        let mut contract = setup_signed(&[(100, 200)]);
+        set_caller("publisher.near", 0, 300);
+        contract.transfer_funds(1);
+
+        resolve_as_contract(PromiseResult::Failed);
+        assert!(!contract.on_transfer_funds(1));
+
+        let presentation = contract.fetch_presentation_by_id(1);
+        let payout = presentation.advertiser_cost - presentation.entertainment_fee;
+        assert_eq!(presentation.status, PresentationStatus::Completed);
+        assert_eq!(contract.fetch_claimable_balance("publisher.near".to_string(), None), payout);
+        let treasury = contract.fetch_treasury(None);
+        assert_eq!(treasury.claimable, payout);
+        assert_eq!(treasury.escrowed, 0);
+        assert_eq!(treasury.fees_earned, presentation.entertainment_fee);
+
+        set_caller("publisher.near", 0, 300);
+        contract.withdraw_claimable(None);
+        assert_eq!(contract.fetch_claimable_balance("publisher.near".to_string(), None), 0);
+        assert_eq!(contract.fetch_treasury(None).claimable, 0);
+
+        resolve_as_contract(PromiseResult::Failed);
+        assert!(!contract.on_withdraw_claimable("publisher.near".to_string(), payout, None));
+        assert_eq!(contract.fetch_claimable_balance("publisher.near".to_string(), None), payout);
+    }
+
+    #[test]
+    fn delivered_payout_leaves_nothing_to_claim() {
+        let mut contract = setup_signed(&[(100, 200)]);
+        set_caller("publisher.near", 0, 300);
+        contract.transfer_funds(1);
+
+        resolve_as_contract(PromiseResult::Successful(vec![]));
+        assert!(contract.on_transfer_funds(1));
+        assert_eq!(contract.fetch_claimable_balance("publisher.near".to_string(), None), 0);
+        assert_eq!(contract.fetch_treasury(None).claimable, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Nothing to claim")]
+    fn nothing_to_claim() {
+        let mut contract = setup_signed(&[]);
+        set_caller("publisher.near", 0, 300);
+        contract.withdraw_claimable(None);
+    }
+}
@@ -0,0 +1,319 @@
+use crate::*;
+
+/// Storage taken by one entry of `storage_accounts`: the longest account id with its length
+/// prefix and the collection prefix, the `StorageAccount` value and the 40 bytes NEAR
+/// charges for every record
+const ACCOUNT_REGISTRATION_BYTES: StorageUsage = 64 + 4 + 1 + 16 + 8 + 40;
+
+/// NEAR deposited by an account to pay for the storage of its records
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct StorageAccount {
+    pub total: Balance,
+    pub used_bytes: StorageUsage,
+}
+
+impl StorageAccount {
+    pub fn available(&self) -> Balance {
+        self.total.saturating_sub(Balance::from(self.used_bytes) * env::storage_byte_cost())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalance {
+    pub total: U128,
+    pub available: U128,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageBalanceBounds {
+    pub min: U128,
+    pub max: Option<U128>,
+}
+
+impl From<&StorageAccount> for StorageBalance {
+    fn from(account: &StorageAccount) -> Self {
+        Self {
+            total: U128(account.total),
+            available: U128(account.available()),
+        }
+    }
+}
+
+fn registration_cost() -> Balance {
+    Balance::from(ACCOUNT_REGISTRATION_BYTES) * env::storage_byte_cost()
+}
+
+/// NEP-145 storage management
+#[near_bindgen]
+impl MetaAdsContract {
+
+    #[payable]
+    pub fn storage_deposit(
+        &mut self,
+        account_id: Option<ValidAccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        let amount: Balance = env::attached_deposit();
+        let account_id: AccountId = account_id
+            .map(|account_id| account_id.into())
+            .unwrap_or_else(env::predecessor_account_id);
+        let registration_only = registration_only.unwrap_or(false);
+
+        let total_before = self.storage_accounts.get(&account_id).map(|account| account.total);
+        let account = match self.storage_accounts.get(&account_id) {
+            Some(mut account) => {
+                if registration_only {
+                    if amount > 0 {
+                        Promise::new(env::predecessor_account_id()).transfer(amount);
+                    }
+                } else {
+                    account.total += amount;
+                }
+                account
+            }
+            None => {
+                let min = registration_cost();
+                assert!(
+                    amount >= min,
+                    "Abort. The attached deposit is less than the minimum storage balance of {}",
+                    min
+                );
+                let total = if registration_only {
+                    if amount > min {
+                        Promise::new(env::predecessor_account_id()).transfer(amount - min);
+                    }
+                    min
+                } else {
+                    amount
+                };
+                StorageAccount { total, used_bytes: ACCOUNT_REGISTRATION_BYTES }
+            }
+        };
+
+        self.storage_accounts.insert(&account_id, &account);
+        emit_storage_deposited(&account_id, account.total - total_before.unwrap_or(0));
+
+        StorageBalance::from(&account)
+    }
+
+    #[payable]
+    pub fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut account = self
+            .storage_accounts
+            .get(&account_id)
+            .unwrap_or_else(|| env::panic(format!("The account {} is not registered", account_id).as_bytes()));
+
+        let available = account.available();
+        let amount: Balance = amount.map(|amount| amount.0).unwrap_or(available);
+        assert!(
+            amount <= available,
+            "Abort. The amount is greater than the available storage balance of {}",
+            available
+        );
+
+        account.total -= amount;
+        self.storage_accounts.insert(&account_id, &account);
+
+        if amount > 0 {
+            EventLog::new(EventLogVariant::StorageWithdrawn(vec![BalanceLog {
+                account_id: account_id.clone(),
+                amount: U128(amount),
+                token_id: None,
+            }]))
+            .emit();
+            Promise::new(account_id).transfer(amount);
+        }
+        StorageBalance::from(&account)
+    }
+
+    pub fn storage_balance_of(&self, account_id: ValidAccountId) -> Option<StorageBalance> {
+        self.storage_accounts
+            .get(account_id.as_ref())
+            .map(|account| StorageBalance::from(&account))
+    }
+
+    pub fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        StorageBalanceBounds {
+            min: U128(registration_cost()),
+            max: None,
+        }
+    }
+}
+
+impl MetaAdsContract {
+    /// Charges `account_id` for the bytes written since `storage_before`, or gives back the
+    /// bytes released. The storage balance pays first and the attached deposit covers the rest.
+    /// Returns the part of `attached` that was not needed.
+    pub(crate) fn internal_update_storage(
+        &mut self,
+        account_id: &AccountId,
+        storage_before: StorageUsage,
+        attached: Balance,
+    ) -> Balance {
+        let storage_after = env::storage_usage();
+
+        if storage_after < storage_before {
+            self.internal_release_storage(account_id, storage_before - storage_after);
+            return attached;
+        }
+
+        let (mut account, bytes) = match self.storage_accounts.get(account_id) {
+            Some(account) => (account, storage_after - storage_before),
+            None => (StorageAccount::default(), storage_after - storage_before + ACCOUNT_REGISTRATION_BYTES),
+        };
+        if bytes == 0 {
+            return attached;
+        }
+
+        let cost: Balance = Balance::from(bytes) * env::storage_byte_cost();
+        let shortfall: Balance = cost.saturating_sub(account.available());
+        assert!(
+            shortfall <= attached,
+            "Abort. Storage of {} bytes costs {}. Attach {} more or call storage_deposit",
+            bytes,
+            cost,
+            shortfall - attached
+        );
+
+        account.total += shortfall;
+        account.used_bytes += bytes;
+        self.storage_accounts.insert(account_id, &account);
+        emit_storage_deposited(account_id, shortfall);
+
+        attached - shortfall
+    }
+
+    /// Frees the bytes for `account_id`, the NEAR becomes available for `storage_withdraw`
+    pub(crate) fn internal_release_storage(&mut self, account_id: &AccountId, bytes: StorageUsage) {
+        // Records written before storage staking were paid for by the contract
+        if let Some(mut account) = self.storage_accounts.get(account_id) {
+            account.used_bytes = account.used_bytes.saturating_sub(bytes).max(ACCOUNT_REGISTRATION_BYTES);
+            self.storage_accounts.insert(account_id, &account);
+        }
+    }
+}
+
+fn emit_storage_deposited(account_id: &AccountId, amount: Balance) {
+    if amount > 0 {
+        EventLog::new(EventLogVariant::StorageDeposited(vec![BalanceLog {
+            account_id: account_id.clone(),
+            amount: U128(amount),
+            token_id: None,
+        }]))
+        .emit();
+    }
+}
+
+/// Sends back the part of the attached deposit a call did not need
+pub(crate) fn refund_unused_deposit(amount: Balance) {
+    refund_deposit_to(&env::predecessor_account_id(), amount);
+}
+
+/// Callbacks run as the contract itself, so they name the account to refund
+pub(crate) fn refund_deposit_to(account_id: &AccountId, amount: Balance) {
+    if amount > 0 {
+        Promise::new(account_id.clone()).transfer(amount);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::test_utils::get_logs;
+
+    fn setup() -> MetaAdsContract {
+        set_caller("alice.near", 0, 0);
+        new_contract()
+    }
+
+    #[test]
+    fn deposit_registers_and_withdraw_keeps_the_used_storage() {
+        let mut contract = setup();
+        set_caller("alice.near", NEAR, 0);
+        let balance = contract.storage_deposit(None, None);
+        assert_eq!(balance.total, U128(NEAR));
+        assert_eq!(balance.available, U128(NEAR - registration_cost()));
+
+        assert!(get_logs()[0].contains(&format!(r#""event":"storage_deposited","data":[{{"account_id":"alice.near","amount":"{}"}}]"#, NEAR)));
+
+        set_caller("alice.near", 1, 0);
+        let balance = contract.storage_withdraw(None);
+        assert_eq!(balance.total, U128(registration_cost()));
+        assert_eq!(balance.available, U128(0));
+        let withdrawn = NEAR - registration_cost();
+        assert!(get_logs()[0].contains(&format!(r#""event":"storage_withdrawn","data":[{{"account_id":"alice.near","amount":"{}"}}]"#, withdrawn)));
+    }
+
+    #[test]
+    fn registration_only_keeps_the_minimum() {
+        let mut contract = setup();
+        set_caller("alice.near", NEAR, 0);
+        let balance = contract.storage_deposit(None, Some(true));
+
+        assert_eq!(balance.total, U128(registration_cost()));
+        assert_eq!(contract.storage_balance_bounds().min, U128(registration_cost()));
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. The attached deposit is less than the minimum storage balance")]
+    fn deposit_below_the_minimum() {
+        let mut contract = setup();
+        set_caller("alice.near", registration_cost() - 1, 0);
+        contract.storage_deposit(None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. The amount is greater than the available storage balance")]
+    fn withdraw_more_than_available() {
+        let mut contract = setup();
+        set_caller("alice.near", NEAR, 0);
+        contract.storage_deposit(None, None);
+
+        set_caller("alice.near", 1, 0);
+        contract.storage_withdraw(Some(U128(NEAR)));
+    }
+
+    #[test]
+    fn update_storage_takes_the_balance_first_then_the_attached_deposit() {
+        let mut contract = setup();
+        let alice = "alice.near".to_string();
+        let byte_cost = env::storage_byte_cost();
+        contract.storage_accounts.insert(
+            &alice,
+            &StorageAccount { total: registration_cost() + 100 * byte_cost, used_bytes: ACCOUNT_REGISTRATION_BYTES },
+        );
+
+        let storage_before = env::storage_usage();
+        env::storage_write(b"record", &[0; 200]);
+        let bytes = env::storage_usage() - storage_before;
+        let shortfall = Balance::from(bytes) * byte_cost - 100 * byte_cost;
+
+        let unused = contract.internal_update_storage(&alice, storage_before, NEAR);
+        assert_eq!(unused, NEAR - shortfall);
+        let account = contract.storage_accounts.get(&alice).unwrap();
+        assert_eq!(account.used_bytes, ACCOUNT_REGISTRATION_BYTES + bytes);
+        assert_eq!(account.available(), 0);
+
+        let storage_before = env::storage_usage();
+        env::storage_remove(b"record");
+        assert_eq!(contract.internal_update_storage(&alice, storage_before, 0), 0);
+        let account = contract.storage_accounts.get(&alice).unwrap();
+        assert_eq!(account.used_bytes, ACCOUNT_REGISTRATION_BYTES);
+        assert_eq!(account.available(), Balance::from(bytes) * byte_cost);
+    }
+
+    #[test]
+    #[should_panic(expected = "Attach")]
+    fn update_storage_without_enough_deposit() {
+        let mut contract = setup();
+        let storage_before = env::storage_usage();
+        env::storage_write(b"record", &[0; 200]);
+
+        contract.internal_update_storage(&"alice.near".to_string(), storage_before, 0);
+    }
+}
@@ -0,0 +1,48 @@
+//! Helpers shared by the unit tests
+use crate::*;
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{testing_env, MockedBlockchain};
+use std::convert::TryInto;
+
+pub const NEAR: Balance = 1_000_000_000_000_000_000_000_000;
+
+/// A context of the metaads.near contract called by `account_id`
+pub fn context(account_id: &str) -> VMContextBuilder {
+    let mut builder = VMContextBuilder::new();
+    builder
+        .current_account_id("metaads.near".to_string().try_into().unwrap())
+        .predecessor_account_id(account_id.to_string().try_into().unwrap());
+    builder
+}
+
+/// Calls the contract as `account_id`, attaching `deposit` at `time` in seconds
+pub fn set_caller(account_id: &str, deposit: Balance, time: Timestamp) {
+    testing_env!(context(account_id)
+        .attached_deposit(deposit)
+        .block_timestamp(time * 1_000_000_000)
+        .build());
+}
+
+/// Calls a callback of the contract with the result of the promise it waited for
+pub fn resolve_as_contract(result: PromiseResult) {
+    testing_env!(
+        context("metaads.near").build(),
+        Default::default(),
+        Default::default(),
+        Default::default(),
+        vec![result]
+    );
+}
+
+/// A contract owned by owner.near with the default platform fee
+pub fn new_contract() -> MetaAdsContract {
+    MetaAdsContract::new(Config { owner_id: "owner.near".to_string(), platform_fee_bps: None })
+}
+
+/// Makes a creative of the caller without an NFT, so it is created right away
+pub fn make_creative(contract: &mut MetaAdsContract, content: &str) -> Creative {
+    match contract.make_creative("Banner".to_string(), content.to_string(), None, None, None, None, None) {
+        PromiseOrValue::Value(Some(creative)) => creative,
+        _ => panic!("creative not created"),
+    }
+}
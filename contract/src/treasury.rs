@@ -0,0 +1,170 @@
+use crate::*;
+
+/// Where the NEAR, or the tokens of one fungible token contract, held by the contract belong
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Default, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct Treasury {
+    /// Deposits of presentations that are not settled, cancelled or refunded yet
+    pub escrowed: Balance,
+    /// Payouts that failed and wait to be withdrawn by their publishers
+    pub claimable: Balance,
+    /// Platform fees kept from every settled presentation
+    pub fees_earned: Balance,
+    pub fees_withdrawn: Balance,
+}
+
+impl Treasury {
+    pub fn fees_available(&self) -> Balance {
+        self.fees_earned - self.fees_withdrawn
+    }
+
+    pub(crate) fn release_escrow(&mut self, amount: Balance) {
+        self.escrowed = self.escrowed.checked_sub(amount).expect("Abort. Escrow underflow");
+    }
+}
+
+#[near_bindgen]
+impl MetaAdsContract {
+
+    /// Withdraws earned platform fees in NEAR, or in `token_id`. Escrowed and claimable
+    /// funds are never touched. Requires 1 yoctoNEAR attached.
+    #[payable]
+    pub fn withdraw_fees(&mut self, amount: Balance, receiver_id: AccountId, token_id: Option<AccountId>) -> Promise {
+        assert_one_yocto();
+        self.assert_owner();
+        assert!(amount > 0, "Abort. Amount undefined");
+        assert!(env::is_valid_account_id(receiver_id.as_bytes()), "Abort. Invalid account id");
+
+        let treasury = self.fetch_treasury(token_id.clone());
+        assert!(
+            amount <= treasury.fees_available(),
+            "Abort. Only {} of fees can be withdrawn",
+            treasury.fees_available()
+        );
+
+        if token_id.is_none() {
+            let owed: Balance = treasury.escrowed + treasury.claimable;
+            assert!(
+                env::account_balance().saturating_sub(owed) >= amount,
+                "Abort. The contract balance does not cover the escrow"
+            );
+        }
+
+        self.internal_treasury_mut(&token_id, |treasury| treasury.fees_withdrawn += amount);
+
+        self.internal_transfer(&receiver_id, amount, &token_id)
+            .then(ext_self::on_withdraw_fees(
+                amount,
+                receiver_id,
+                token_id,
+                &env::current_account_id(),
+                0,
+                GAS_FOR_RESOLVE_TRANSFER,
+            ))
+    }
+
+    #[private]
+    pub fn on_withdraw_fees(&mut self, amount: Balance, receiver_id: AccountId, token_id: Option<AccountId>) -> bool {
+        assert_eq!(env::promise_results_count(), 1, "Abort. Expected one promise result");
+
+        match env::promise_result(0) {
+            PromiseResult::Successful(_) => {
+                log!("Withdrew {} of fees to {}", amount, receiver_id);
+                EventLog::new(EventLogVariant::FeesWithdrawn(vec![BalanceLog {
+                    account_id: receiver_id,
+                    amount: U128(amount),
+                    token_id,
+                }]))
+                .emit();
+                true
+            }
+            _ => {
+                self.internal_treasury_mut(&token_id, |treasury| treasury.fees_withdrawn -= amount);
+                false
+            }
+        }
+    }
+
+    /// The accounting of NEAR, or of `token_id`
+    pub fn fetch_treasury(&self, token_id: Option<AccountId>) -> Treasury {
+        match token_id {
+            Some(token_id) => self.token_treasuries.get(&token_id).unwrap_or_default(),
+            None => self.treasury.clone(),
+        }
+    }
+}
+
+impl MetaAdsContract {
+    pub(crate) fn internal_treasury_mut<F>(&mut self, token_id: &Option<AccountId>, update: F)
+    where
+        F: FnOnce(&mut Treasury),
+    {
+        match token_id {
+            Some(token_id) => {
+                let mut treasury = self.token_treasuries.get(token_id).unwrap_or_default();
+                update(&mut treasury);
+                self.token_treasuries.insert(token_id, &treasury);
+            }
+            None => update(&mut self.treasury),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::*;
+    use near_sdk::{testing_env, MockedBlockchain};
+
+    /// The owner holds `account_balance`, 10 NEAR of which are escrowed and 1 NEAR of fees earned
+    fn setup(account_balance: Balance) -> MetaAdsContract {
+        testing_env!(context("owner.near").account_balance(account_balance).attached_deposit(1).build());
+        let mut contract = new_contract();
+        contract.treasury.escrowed = 10 * NEAR;
+        contract.treasury.fees_earned = NEAR;
+        contract
+    }
+
+    #[test]
+    fn fees_are_withdrawn_up_to_the_earned_amount() {
+        let mut contract = setup(20 * NEAR);
+        contract.withdraw_fees(NEAR / 2, "treasury.near".to_string(), None);
+
+        let treasury = contract.fetch_treasury(None);
+        assert_eq!(treasury.fees_withdrawn, NEAR / 2);
+        assert_eq!(treasury.fees_available(), NEAR / 2);
+        assert_eq!(treasury.escrowed, 10 * NEAR);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. Only 1000000000000000000000000 of fees can be withdrawn")]
+    fn fees_beyond_the_earned_amount() {
+        let mut contract = setup(20 * NEAR);
+        contract.withdraw_fees(2 * NEAR, "treasury.near".to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of exactly 1 yoctoNEAR")]
+    fn fees_need_one_yocto() {
+        let mut contract = setup(20 * NEAR);
+        set_caller("owner.near", 0, 0);
+        contract.withdraw_fees(NEAR, "treasury.near".to_string(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Abort. The contract balance does not cover the escrow")]
+    fn fees_never_come_out_of_the_escrow() {
+        let mut contract = setup(10 * NEAR + NEAR / 2);
+        contract.withdraw_fees(NEAR, "treasury.near".to_string(), None);
+    }
+
+    #[test]
+    fn failed_withdrawal_restores_the_fees() {
+        let mut contract = setup(20 * NEAR);
+        contract.withdraw_fees(NEAR, "treasury.near".to_string(), None);
+
+        resolve_as_contract(PromiseResult::Failed);
+        assert!(!contract.on_withdraw_fees(NEAR, "treasury.near".to_string(), None));
+        assert_eq!(contract.fetch_treasury(None).fees_available(), NEAR);
+    }
+}